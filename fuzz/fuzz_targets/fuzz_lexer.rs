@@ -0,0 +1,19 @@
+#![no_main]
+
+use c_compiler_edu::lexer::Lexer;
+use libfuzzer_sys::fuzz_target;
+
+// どんな入力でもパニックせず、トークン列かエラーを返すことを確かめる
+fuzz_target!(|data: &[u8]| {
+    // 入力はUTF-8の文字列として受け取るので、それ以外のバイト列は試さない
+    let Ok(input) = std::str::from_utf8(data) else {
+        return;
+    };
+    let input = input.to_string();
+    let mut lexer = Lexer::new(&input);
+
+    // エラーの位置を示す表示もパニックしないことを確かめる
+    if let Err(err) = lexer.tokenize() {
+        let _ = lexer.error_at(&err);
+    }
+});
@@ -1,62 +1,908 @@
-use crate::parser::{Node, NodeKind};
+use crate::{
+    ctype::CType,
+    error::CompileError,
+    parser::{Node, NodeKind},
+};
 
-pub fn gen(node: Node) {
-    let node_kind = node.get_kind();
+/// 命令の字下げ
+const INDENT: &str = "  ";
 
-    if let NodeKind::Num(num) = node_kind {
-        println!("  push {}", num);
-        return;
+/// 命令を字下げして1行出力する
+/// 命令の文字列には先頭の空白も末尾の改行も含めない
+pub fn emit(out: &mut String, instr: &str) {
+    out.push_str(INDENT);
+    out.push_str(instr);
+    out.push('\n');
+}
+
+/// ラベルやディレクティブを字下げせずに1行出力する
+pub fn emit_label(out: &mut String, label: &str) {
+    out.push_str(label);
+    out.push('\n');
+}
+
+/// `n`を`align`の倍数に切り上げる
+pub(crate) fn align_to(n: usize, align: usize) -> usize {
+    n.div_ceil(align) * align
+}
+
+/// 重複しないラベルを生成する
+#[derive(Default)]
+pub struct LabelGen {
+    count: usize, // これまでに生成したラベルの数
+}
+
+impl LabelGen {
+    pub fn new() -> LabelGen {
+        LabelGen::default()
     }
 
-    if let Some(lhs) = node.get_lhs() {
-        gen(*lhs);
-    };
+    /// `.L.<prefix>.<番号>`の形のラベルを返す
+    /// 番号はprefixによらず通し番号なので、同じprefixでも異なるラベルになる
+    pub fn next(&mut self, prefix: &str) -> String {
+        let label = format!(".L.{}.{}", prefix, self.count);
 
-    if let Some(rhs) = node.get_rhs() {
-        gen(*rhs);
-    };
+        self.count += 1;
 
-    println!("  pop rdi");
-    println!("  pop rax");
+        label
+    }
+}
 
-    match node_kind {
-        NodeKind::Add => {
-            println!("  add rax, rdi");
+/// 文字列リテラルとそのラベルの対応表
+/// 同じ内容の文字列リテラルには同じラベルを割り当てる
+#[derive(Default)]
+pub struct StringTable {
+    literals: Vec<(Vec<u8>, String)>, // 文字列リテラルのバイト列とラベル
+}
+
+impl StringTable {
+    pub fn new() -> StringTable {
+        StringTable::default()
+    }
+
+    /// 文字列リテラルに対応するラベルを返す
+    /// 初めて現れた文字列リテラルなら新しいラベルを割り当てる
+    pub fn label(&mut self, bytes: &[u8], labels: &mut LabelGen) -> String {
+        if let Some((_, label)) = self.literals.iter().find(|(literal, _)| literal == bytes) {
+            return label.clone();
         }
-        NodeKind::Sub => {
-            println!("  sub rax, rdi");
+
+        let label = labels.next("str");
+
+        self.literals.push((bytes.to_vec(), label.clone()));
+
+        label
+    }
+
+    /// 登録された文字列リテラルを.rodataセクションに出力する
+    pub fn gen_rodata(&self, out: &mut String) {
+        if self.literals.is_empty() {
+            return;
         }
-        NodeKind::Mul => {
-            println!("  imul rax, rdi");
+
+        emit_label(out, ".section .rodata");
+
+        for (literal, label) in &self.literals {
+            // 終端のヌル文字を付け加える
+            let bytes: Vec<String> = literal
+                .iter()
+                .chain(&[0])
+                .map(|byte| byte.to_string())
+                .collect();
+
+            emit_label(out, &format!("{}:", label));
+            emit(out, &format!(".byte {}", bytes.join(", ")));
         }
-        NodeKind::Div => {
-            println!("  cqo");
-            println!("  idiv rdi");
+    }
+}
+
+/// 出力するアセンブリの記法
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyntaxMode {
+    #[default]
+    Intel, // `mov rax, [rdi]`のように書き込み先を先に書く
+    Att, // `mov (%rdi), %rax`のように書き込み先を後に書く
+}
+
+/// 命令の被演算子
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Reg(&'static str),     // レジスタ
+    Imm(isize),            // 即値
+    Mem(&'static str),     // レジスタが指すメモリ
+    ByteMem(&'static str), // レジスタが指すメモリの1バイト
+    WordMem(&'static str), // レジスタが指すメモリの2バイト
+    Rip(String),           // RIPからの相対位置で表すラベルのアドレス
+}
+
+impl Operand {
+    fn format(&self, syntax: SyntaxMode) -> String {
+        match (self, syntax) {
+            (Operand::Reg(reg), SyntaxMode::Intel) => reg.to_string(),
+            (Operand::Reg(reg), SyntaxMode::Att) => format!("%{}", reg),
+            (Operand::Imm(num), SyntaxMode::Intel) => num.to_string(),
+            (Operand::Imm(num), SyntaxMode::Att) => format!("${}", num),
+            (Operand::Mem(reg), SyntaxMode::Intel) => format!("[{}]", reg),
+            (Operand::ByteMem(reg), SyntaxMode::Intel) => format!("byte ptr [{}]", reg),
+            (Operand::WordMem(reg), SyntaxMode::Intel) => format!("word ptr [{}]", reg),
+            // AT&T記法ではメモリの大きさを命令の接尾辞で表す
+            (
+                Operand::Mem(reg) | Operand::ByteMem(reg) | Operand::WordMem(reg),
+                SyntaxMode::Att,
+            ) => format!("(%{})", reg),
+            (Operand::Rip(label), SyntaxMode::Intel) => format!("[rip + {}]", label),
+            (Operand::Rip(label), SyntaxMode::Att) => format!("{}(%rip)", label),
         }
-        NodeKind::Eq => {
-            println!("  cmp rax, rdi");
-            println!("  sete al");
-            println!("  movzb rax, al");
+    }
+
+    /// 2バイトのレジスタまたはメモリかどうか
+    fn is_word(&self) -> bool {
+        matches!(self, Operand::Reg("ax") | Operand::WordMem(_))
+    }
+}
+
+// よく使うレジスタ
+const RAX: Operand = Operand::Reg("rax");
+const RDI: Operand = Operand::Reg("rdi");
+const RBP: Operand = Operand::Reg("rbp");
+const RSP: Operand = Operand::Reg("rsp");
+const AL: Operand = Operand::Reg("al");
+const AX: Operand = Operand::Reg("ax");
+
+/// 比較の結果をレジスタに書き込むときの条件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cond {
+    Eq,         // 等しい
+    Ne,         // 等しくない
+    Lt,         // 符号付きで小さい
+    Le,         // 符号付きで以下
+    Below,      // 符号なしで小さい
+    BelowEqual, // 符号なしで以下
+}
+
+/// x86-64の命令
+/// 被演算子はIntel記法の順(書き込み先が先)で持ち、記法は出力するときに決める
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inst {
+    Globl(String), // シンボルを外部から参照できるようにする`.globl`
+    Label(String), // ラベル
+    Push(Operand),
+    Pop(Operand),
+    Mov(Operand, Operand),
+    Movsx(Operand, Operand), // 1バイトか2バイトを符号拡張して転送する
+    Movzx(Operand, Operand), // 1バイトか2バイトをゼロ拡張して転送する
+    Lea(Operand, Operand),
+    Add(Operand, Operand),
+    Sub(Operand, Operand),
+    Imul(Operand, Operand),
+    Cqo, // RAXを符号拡張してRDX:RAXの128ビットにする
+    Idiv(Operand),
+    Div(Operand), // 符号なしの除算
+    Sal(Operand, Operand),
+    Cmp(Operand, Operand),
+    Set(Cond, Operand),
+    Jmp(String),
+    Ret,
+}
+
+impl Inst {
+    /// 命令を記法に合わせた1行のアセンブリにする
+    /// 命令は字下げし、末尾の改行は含めない
+    pub fn format(&self, syntax: SyntaxMode) -> String {
+        let (op, operands): (&str, Vec<&Operand>) = match self {
+            Inst::Globl(name) => return format!(".globl {}", name),
+            Inst::Label(label) => return format!("{}:", label),
+            Inst::Jmp(label) => return format!("{}jmp {}", INDENT, label),
+            Inst::Push(src) => ("push", vec![src]),
+            Inst::Pop(dst) => ("pop", vec![dst]),
+            Inst::Mov(dst, src) => ("mov", vec![dst, src]),
+            // AT&T記法では転送元と転送先の大きさを接尾辞で表す
+            Inst::Movsx(dst, src) => match syntax {
+                SyntaxMode::Intel => ("movsx", vec![dst, src]),
+                SyntaxMode::Att if src.is_word() => ("movswq", vec![dst, src]),
+                SyntaxMode::Att => ("movsbq", vec![dst, src]),
+            },
+            Inst::Movzx(dst, src) if src.is_word() => match syntax {
+                SyntaxMode::Intel => ("movzx", vec![dst, src]),
+                SyntaxMode::Att => ("movzwq", vec![dst, src]),
+            },
+            // 1バイトのレジスタからの転送はどちらの記法でも`movzb`と書く
+            Inst::Movzx(dst, src @ Operand::Reg(_)) => ("movzb", vec![dst, src]),
+            Inst::Movzx(dst, src) => match syntax {
+                SyntaxMode::Intel => ("movzx", vec![dst, src]),
+                SyntaxMode::Att => ("movzbq", vec![dst, src]),
+            },
+            Inst::Lea(dst, src) => ("lea", vec![dst, src]),
+            Inst::Add(dst, src) => ("add", vec![dst, src]),
+            Inst::Sub(dst, src) => ("sub", vec![dst, src]),
+            Inst::Imul(dst, src) => ("imul", vec![dst, src]),
+            Inst::Cqo => ("cqo", vec![]),
+            Inst::Idiv(src) => ("idiv", vec![src]),
+            Inst::Div(src) => ("div", vec![src]),
+            Inst::Sal(dst, src) => ("sal", vec![dst, src]),
+            Inst::Cmp(lhs, rhs) => ("cmp", vec![lhs, rhs]),
+            Inst::Set(cond, dst) => {
+                let op = match cond {
+                    Cond::Eq => "sete",
+                    Cond::Ne => "setne",
+                    Cond::Lt => "setl",
+                    Cond::Le => "setle",
+                    Cond::Below => "setb",
+                    Cond::BelowEqual => "setbe",
+                };
+
+                (op, vec![dst])
+            }
+            Inst::Ret => ("ret", vec![]),
+        };
+
+        let mut operands: Vec<String> = operands
+            .iter()
+            .map(|operand| operand.format(syntax))
+            .collect();
+
+        if syntax == SyntaxMode::Att {
+            operands.reverse();
         }
-        NodeKind::Ne => {
-            println!("  cmp rax, rdi");
-            println!("  setne al");
-            println!("  movzb rax, al");
+
+        if operands.is_empty() {
+            format!("{}{}", INDENT, op)
+        } else {
+            format!("{}{} {}", INDENT, op, operands.join(", "))
         }
-        NodeKind::Lt => {
-            println!("  cmp rax, rdi");
-            println!("  setl al");
-            println!("  movzb rax, al");
+    }
+}
+
+/// 命令列を記法に合わせたアセンブリにする
+fn render(insts: &[Inst], syntax: SyntaxMode) -> String {
+    let mut out = String::new();
+
+    for inst in insts {
+        out.push_str(&inst.format(syntax));
+        out.push('\n');
+    }
+
+    out
+}
+
+/// 命令列をIntel記法のアセンブリにする
+pub fn emit_intel(insts: &[Inst]) -> String {
+    render(insts, SyntaxMode::Intel)
+}
+
+/// 命令列をAT&T記法のアセンブリにする
+pub fn emit_att(insts: &[Inst]) -> String {
+    render(insts, SyntaxMode::Att)
+}
+
+/// 生成した命令を順に保持する
+/// 生成し終えてから、隣り合う命令をのぞき穴最適化でまとめられる
+#[derive(Default)]
+pub struct AsmBuffer {
+    insts: Vec<Inst>, // 生成した命令
+}
+
+impl AsmBuffer {
+    pub fn new() -> AsmBuffer {
+        AsmBuffer::default()
+    }
+
+    /// 命令を1つ追加する
+    pub fn push(&mut self, inst: Inst) {
+        self.insts.push(inst);
+    }
+
+    /// 保持している命令
+    pub fn insts(&self) -> &[Inst] {
+        &self.insts
+    }
+
+    /// 保持している命令の数
+    pub fn len(&self) -> usize {
+        self.insts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.insts.is_empty()
+    }
+
+    /// 積んだ直後に取り出す`push`と`pop`の組をまとめる
+    /// `push rax`と`pop rdi`は`mov rdi, rax`に置き換え、同じレジスタなら両方を取り除く
+    /// 間にラベルがあると飛んでくる経路があるので、隣り合う命令だけを対象にする
+    pub fn peephole_optimize(&mut self) {
+        let mut insts = Vec::with_capacity(self.insts.len());
+        let mut iter = std::mem::take(&mut self.insts).into_iter().peekable();
+
+        while let Some(inst) = iter.next() {
+            if let (Inst::Push(src), Some(Inst::Pop(dst))) = (&inst, iter.peek()) {
+                if src != dst {
+                    insts.push(Inst::Mov(dst.clone(), src.clone()));
+                }
+
+                iter.next();
+                continue;
+            }
+
+            insts.push(inst);
         }
-        NodeKind::Le => {
-            println!("  cmp rax, rdi");
-            println!("  setle al");
-            println!("  movzb rax, al");
+
+        self.insts = insts;
+    }
+}
+
+/// コード生成器
+/// 出力するアセンブリと、生成中に必要になるラベルや文字列リテラルの情報を持つ
+#[derive(Default)]
+pub struct Codegen {
+    out: AsmBuffer,       // 出力する命令列
+    syntax: SyntaxMode,   // 出力するアセンブリの記法
+    labels: LabelGen,     // ラベルの生成器
+    strings: StringTable, // 文字列リテラルの表
+    return_label: String, // 生成中の関数から戻るときの飛び先
+}
+
+impl Codegen {
+    pub fn new(syntax: SyntaxMode) -> Codegen {
+        Codegen {
+            out: AsmBuffer::new(),
+            syntax,
+            labels: LabelGen::new(),
+            strings: StringTable::new(),
+            return_label: String::new(),
         }
-        _ => {
-            panic!("予期しないノードです")
+    }
+
+    /// 命令を1つ出力する
+    fn inst(&mut self, inst: Inst) {
+        self.out.push(inst);
+    }
+
+    /// 式全体を`main`関数の返り値とするプログラムを生成し、アセンブリを返す
+    /// 生成した命令列にはのぞき穴最適化をかける
+    pub fn gen_program(mut self, node: Node) -> Result<String, CompileError> {
+        let mut asm = String::new();
+
+        // GNU asの既定はAT&T記法なので、Intel記法のときだけ切り替える
+        if self.syntax == SyntaxMode::Intel {
+            emit_label(&mut asm, ".intel_syntax noprefix");
         }
+
+        // 局所変数はまだないので、スタックフレームに領域は要らない
+        self.gen_func("main", node, 0)?;
+
+        self.out.peephole_optimize();
+
+        asm.push_str(&render(self.out.insts(), self.syntax));
+
+        // 式中に現れた文字列リテラルを出力
+        self.strings.gen_rodata(&mut asm);
+
+        Ok(asm)
+    }
+
+    /// 関数のコードを生成する
+    /// `locals_size`は局所変数に必要なバイト数で、RSPを16の倍数に保つように切り上げて確保する
+    pub fn gen_func(
+        &mut self,
+        name: &str,
+        body: Node,
+        locals_size: usize,
+    ) -> Result<(), CompileError> {
+        self.return_label = format!(".L.return.{}", name);
+
+        self.inst(Inst::Globl(name.to_string()));
+        self.inst(Inst::Label(name.to_string()));
+
+        // プロローグ
+        self.inst(Inst::Push(RBP));
+        self.inst(Inst::Mov(RBP, RSP));
+        self.inst(Inst::Sub(
+            RSP,
+            Operand::Imm(align_to(locals_size, 16) as isize),
+        ));
+
+        self.gen_stmt(body)?;
+
+        // エピローグ
+        // 返り値はRAXに入っている
+        self.inst(Inst::Label(self.return_label.clone()));
+        self.inst(Inst::Mov(RSP, RBP));
+        self.inst(Inst::Pop(RBP));
+        self.inst(Inst::Ret);
+
+        Ok(())
+    }
+
+    /// 文のコードを生成する
+    /// 文はスタックに値を残さない
+    /// 今のところ文は式1つだけで、その値をRAXに入れて関数からの返り値とする
+    pub fn gen_stmt(&mut self, node: Node) -> Result<(), CompileError> {
+        self.gen_expr(node)?;
+
+        self.inst(Inst::Pop(RAX));
+
+        Ok(())
     }
 
-    println!("  push rax")
+    /// 左辺値のアドレスをスタックに積む
+    fn gen_lval(&mut self, node: Node) -> Result<(), CompileError> {
+        match node.get_kind() {
+            NodeKind::Deref => {
+                if let Some(lhs) = node.get_lhs() {
+                    self.gen_expr(*lhs)?;
+                }
+
+                Ok(())
+            }
+            _ => Err(CompileError::CodegenError {
+                message: "左辺値ではありません".to_string(),
+            }),
+        }
+    }
+
+    /// スタックトップのアドレスから値を読み込み、その値で置き換える
+    /// 配列は先頭のアドレスがそのまま値になるので、何も読み込まない
+    fn load(&mut self, ty: &CType) {
+        if let CType::Array(..) = ty {
+            return;
+        }
+
+        self.inst(Inst::Pop(RAX));
+
+        match ty {
+            CType::Char => self.inst(Inst::Movsx(RAX, Operand::ByteMem("rax"))),
+            CType::Short => self.inst(Inst::Movsx(RAX, Operand::WordMem("rax"))),
+            CType::UChar => self.inst(Inst::Movzx(RAX, Operand::ByteMem("rax"))),
+            CType::UShort => self.inst(Inst::Movzx(RAX, Operand::WordMem("rax"))),
+            CType::Bool => self.inst(Inst::Movzx(RAX, Operand::ByteMem("rax"))),
+            _ => self.inst(Inst::Mov(RAX, Operand::Mem("rax"))),
+        }
+
+        self.inst(Inst::Push(RAX));
+    }
+
+    /// 式のコードを生成する
+    /// 式の値はスタックに1つだけ積まれる
+    pub fn gen_expr(&mut self, node: Node) -> Result<(), CompileError> {
+        let node_kind = node.get_kind();
+
+        if let NodeKind::Addr = node_kind {
+            if let Some(lhs) = node.get_lhs() {
+                self.gen_lval(*lhs)?;
+            }
+            return Ok(());
+        }
+
+        if let NodeKind::Deref = node_kind {
+            let ty = node.get_type();
+
+            self.gen_lval(node)?;
+            self.load(&ty);
+            return Ok(());
+        }
+
+        if let NodeKind::Cast(ty) = node_kind {
+            if let Some(lhs) = node.get_lhs() {
+                self.gen_expr(*lhs)?;
+            }
+
+            // int、longとポインタはどれも8バイトなので、値を変えるのは1バイトと2バイトの整数と_Boolへの変換だけ
+            match ty {
+                // 下位1バイトを符号拡張して残りを捨てる
+                CType::Char => {
+                    self.inst(Inst::Pop(RAX));
+                    self.inst(Inst::Movsx(RAX, AL));
+                    self.inst(Inst::Push(RAX));
+                }
+                // 下位2バイトを符号拡張して残りを捨てる
+                CType::Short => {
+                    self.inst(Inst::Pop(RAX));
+                    self.inst(Inst::Movsx(RAX, AX));
+                    self.inst(Inst::Push(RAX));
+                }
+                // 符号なしの型へはゼロ拡張する
+                CType::UChar => {
+                    self.inst(Inst::Pop(RAX));
+                    self.inst(Inst::Movzx(RAX, AL));
+                    self.inst(Inst::Push(RAX));
+                }
+                CType::UShort => {
+                    self.inst(Inst::Pop(RAX));
+                    self.inst(Inst::Movzx(RAX, AX));
+                    self.inst(Inst::Push(RAX));
+                }
+                // 0でなければ1にする
+                CType::Bool => {
+                    self.inst(Inst::Pop(RAX));
+                    self.inst(Inst::Cmp(RAX, Operand::Imm(0)));
+                    self.inst(Inst::Set(Cond::Ne, AL));
+                    self.inst(Inst::Movzx(RAX, AL));
+                    self.inst(Inst::Push(RAX));
+                }
+                _ => {}
+            }
+
+            return Ok(());
+        }
+
+        if let NodeKind::Num(num) = node_kind {
+            // pushの即値は32ビットまでなので、収まらない数はRAXを経由して積む
+            if i32::try_from(num).is_ok() {
+                self.inst(Inst::Push(Operand::Imm(num)));
+            } else {
+                self.inst(Inst::Mov(RAX, Operand::Imm(num)));
+                self.inst(Inst::Push(RAX));
+            }
+
+            return Ok(());
+        }
+
+        if let NodeKind::Str(bytes) = node_kind {
+            let label = self.strings.label(&bytes, &mut self.labels);
+
+            self.inst(Inst::Lea(RAX, Operand::Rip(label)));
+            self.inst(Inst::Push(RAX));
+            return Ok(());
+        }
+
+        // シフト量が定数なら即値で埋め込む
+        if let (NodeKind::Shl, Some(lhs), Some(NodeKind::Num(count))) = (
+            &node_kind,
+            node.get_lhs(),
+            node.get_rhs().map(|rhs| rhs.get_kind()),
+        ) {
+            self.gen_expr(*lhs)?;
+            self.inst(Inst::Pop(RAX));
+            self.inst(Inst::Sal(RAX, Operand::Imm(count)));
+            self.inst(Inst::Push(RAX));
+            return Ok(());
+        }
+
+        let unsigned = node.has_unsigned_operands();
+
+        if let Some(lhs) = node.get_lhs() {
+            self.gen_expr(*lhs)?;
+        };
+
+        if let Some(rhs) = node.get_rhs() {
+            self.gen_expr(*rhs)?;
+        };
+
+        self.inst(Inst::Pop(RDI));
+        self.inst(Inst::Pop(RAX));
+
+        match node_kind {
+            NodeKind::Add => {
+                self.inst(Inst::Add(RAX, RDI));
+            }
+            NodeKind::Sub => {
+                self.inst(Inst::Sub(RAX, RDI));
+            }
+            NodeKind::Mul => {
+                self.inst(Inst::Imul(RAX, RDI));
+            }
+            // 符号なしの除算では、RDX:RAXの上位をゼロにする
+            NodeKind::Div if unsigned => {
+                self.inst(Inst::Mov(Operand::Reg("rdx"), Operand::Imm(0)));
+                self.inst(Inst::Div(RDI));
+            }
+            NodeKind::Div => {
+                self.inst(Inst::Cqo);
+                self.inst(Inst::Idiv(RDI));
+            }
+            NodeKind::Shl => {
+                // シフト量はCLでしか指定できない
+                self.inst(Inst::Mov(Operand::Reg("rcx"), RDI));
+                self.inst(Inst::Sal(RAX, Operand::Reg("cl")));
+            }
+            NodeKind::Eq | NodeKind::Ne | NodeKind::Lt | NodeKind::Le => {
+                let cond = match (node_kind, unsigned) {
+                    (NodeKind::Eq, _) => Cond::Eq,
+                    (NodeKind::Ne, _) => Cond::Ne,
+                    (NodeKind::Lt, false) => Cond::Lt,
+                    (NodeKind::Lt, true) => Cond::Below,
+                    (_, false) => Cond::Le,
+                    (_, true) => Cond::BelowEqual,
+                };
+
+                self.inst(Inst::Cmp(RAX, RDI));
+                self.inst(Inst::Set(cond, AL));
+                self.inst(Inst::Movzx(RAX, AL));
+            }
+            _ => {
+                return Err(CompileError::CodegenError {
+                    message: "予期しないノードです".to_string(),
+                });
+            }
+        }
+
+        self.inst(Inst::Push(RAX));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        emit_att, emit_intel, AsmBuffer, Codegen, Cond, Inst, LabelGen, Operand, StringTable,
+        SyntaxMode,
+    };
+    use crate::{
+        lexer::Lexer,
+        parser::{Node, Parser},
+    };
+
+    fn parse(input: &str) -> Node {
+        let input = input.to_string();
+        let mut lexer = Lexer::new(&input);
+
+        lexer.tokenize().unwrap();
+
+        Parser::new(lexer).expr().ok().unwrap()
+    }
+
+    #[test]
+    fn gen_indent() {
+        let mut codegen = Codegen::new(SyntaxMode::Intel);
+
+        codegen.gen_expr(parse("1+2")).unwrap();
+
+        assert_eq!(
+            "  push 1\n  push 2\n  pop rdi\n  pop rax\n  add rax, rdi\n  push rax\n",
+            emit_intel(codegen.out.insts())
+        );
+    }
+
+    #[test]
+    fn gen_program() {
+        let expected = "\
+.intel_syntax noprefix
+.globl main
+main:
+  push rbp
+  mov rbp, rsp
+  sub rsp, 0
+  push 2
+  push 3
+  mov rdi, 4
+  pop rax
+  imul rax, rdi
+  mov rdi, rax
+  pop rax
+  add rax, rdi
+.L.return.main:
+  mov rsp, rbp
+  pop rbp
+  ret
+";
+
+        assert_eq!(
+            expected,
+            Codegen::new(SyntaxMode::Intel)
+                .gen_program(parse("2+3*4"))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn gen_program_att() {
+        let expected = "\
+.globl main
+main:
+  push %rbp
+  mov %rsp, %rbp
+  sub $0, %rsp
+  push $1
+  mov $2, %rdi
+  pop %rax
+  cmp %rdi, %rax
+  setl %al
+  movzb %al, %rax
+.L.return.main:
+  mov %rbp, %rsp
+  pop %rbp
+  ret
+";
+
+        assert_eq!(
+            expected,
+            Codegen::new(SyntaxMode::Att)
+                .gen_program(parse("1<2"))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn align_to() {
+        assert_eq!(0, super::align_to(0, 16));
+        assert_eq!(16, super::align_to(1, 16));
+        assert_eq!(16, super::align_to(16, 16));
+        assert_eq!(32, super::align_to(17, 16));
+    }
+
+    #[test]
+    fn gen_func_frame() {
+        let mut codegen = Codegen::new(SyntaxMode::Intel);
+
+        codegen.gen_func("f", parse("1"), 20).unwrap();
+
+        let asm = emit_intel(codegen.out.insts());
+
+        assert!(asm.contains("  sub rsp, 32\n"));
+        assert!(asm.ends_with(".L.return.f:\n  mov rsp, rbp\n  pop rbp\n  ret\n"));
+    }
+
+    #[test]
+    fn peephole_optimize() {
+        let rax = Operand::Reg("rax");
+        let rdi = Operand::Reg("rdi");
+        let mut buf = AsmBuffer::new();
+
+        buf.push(Inst::Push(Operand::Imm(1)));
+        buf.push(Inst::Pop(rdi.clone()));
+        buf.push(Inst::Push(rax.clone()));
+        buf.push(Inst::Pop(rax.clone()));
+        buf.push(Inst::Push(rax.clone()));
+        buf.push(Inst::Label(".L.end.0".to_string()));
+        buf.push(Inst::Pop(rdi.clone()));
+        buf.peephole_optimize();
+
+        // ラベルをまたぐ組はそのまま残す
+        assert_eq!(
+            &[
+                Inst::Mov(rdi.clone(), Operand::Imm(1)),
+                Inst::Push(rax.clone()),
+                Inst::Label(".L.end.0".to_string()),
+                Inst::Pop(rdi.clone()),
+            ],
+            buf.insts()
+        );
+
+        let mut buf = AsmBuffer::new();
+
+        buf.push(Inst::Push(rax));
+        buf.push(Inst::Pop(rdi));
+        buf.peephole_optimize();
+
+        assert_eq!("  mov %rax, %rdi\n", emit_att(buf.insts()));
+    }
+
+    #[test]
+    fn render_both_syntaxes() {
+        let rax = Operand::Reg("rax");
+        let insts = [
+            Inst::Label(".L.begin.0".to_string()),
+            Inst::Mov(rax.clone(), Operand::Mem("rdi")),
+            Inst::Movsx(rax.clone(), Operand::ByteMem("rax")),
+            Inst::Movsx(rax.clone(), Operand::WordMem("rax")),
+            Inst::Movsx(rax.clone(), Operand::Reg("ax")),
+            Inst::Movzx(rax.clone(), Operand::WordMem("rax")),
+            Inst::Movzx(rax.clone(), Operand::Reg("ax")),
+            Inst::Set(Cond::Below, Operand::Reg("al")),
+            Inst::Div(Operand::Reg("rdi")),
+            Inst::Cmp(rax.clone(), Operand::Imm(0)),
+            Inst::Set(Cond::Ne, Operand::Reg("al")),
+            Inst::Movzx(rax.clone(), Operand::Reg("al")),
+            Inst::Lea(rax, Operand::Rip(".L.str.1".to_string())),
+            Inst::Jmp(".L.begin.0".to_string()),
+        ];
+
+        assert_eq!(
+            "\
+.L.begin.0:
+  mov rax, [rdi]
+  movsx rax, byte ptr [rax]
+  movsx rax, word ptr [rax]
+  movsx rax, ax
+  movzx rax, word ptr [rax]
+  movzx rax, ax
+  setb al
+  div rdi
+  cmp rax, 0
+  setne al
+  movzb rax, al
+  lea rax, [rip + .L.str.1]
+  jmp .L.begin.0
+",
+            emit_intel(&insts)
+        );
+        assert_eq!(
+            "\
+.L.begin.0:
+  mov (%rdi), %rax
+  movsbq (%rax), %rax
+  movswq (%rax), %rax
+  movswq %ax, %rax
+  movzwq (%rax), %rax
+  movzwq %ax, %rax
+  setb %al
+  div %rdi
+  cmp $0, %rax
+  setne %al
+  movzb %al, %rax
+  lea .L.str.1(%rip), %rax
+  jmp .L.begin.0
+",
+            emit_att(&insts)
+        );
+    }
+
+    #[test]
+    fn gen_round_trip() {
+        // 生成した命令列を描画すると、プログラム全体の出力と同じ本体になる
+        let mut codegen = Codegen::new(SyntaxMode::Intel);
+
+        codegen.gen_func("main", parse("*\"a\" == 97"), 0).unwrap();
+        codegen.out.peephole_optimize();
+
+        let body = emit_intel(codegen.out.insts());
+        let program = Codegen::new(SyntaxMode::Intel)
+            .gen_program(parse("*\"a\" == 97"))
+            .unwrap();
+
+        assert!(program.starts_with(&format!(".intel_syntax noprefix\n{}", body)));
+    }
+
+    #[test]
+    fn peephole_nested() {
+        // 深く入れ子になった式ほど、積んですぐ取り出す組が多く消える
+        let input = "((((1+2)*(3+4))-((5+6)*(7+8)))/(((9+10)*(11+12))-((13+14)*(15+16))))";
+        let mut codegen = Codegen::new(SyntaxMode::Intel);
+
+        codegen.gen_expr(parse(input)).unwrap();
+
+        let before = codegen.out.len();
+
+        codegen.out.peephole_optimize();
+
+        assert_eq!(77, before);
+        assert_eq!(62, codegen.out.len());
+    }
+
+    #[test]
+    fn peephole_small_expressions() {
+        // (式, 最適化前の命令数, 最適化後の命令数)
+        let cases = [
+            ("1+2", 6, 5),
+            ("1<2", 8, 7),
+            ("-3", 6, 5),
+            ("(1+2)*3", 11, 9),
+            (r#"*"a""#, 5, 3),
+        ];
+
+        for (input, before, after) in cases {
+            let mut codegen = Codegen::new(SyntaxMode::Intel);
+
+            codegen.gen_expr(parse(input)).unwrap();
+            assert_eq!(before, codegen.out.len(), "{}", input);
+
+            codegen.out.peephole_optimize();
+            assert_eq!(after, codegen.out.len(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn label_gen_next() {
+        let mut labels = LabelGen::new();
+
+        let first = labels.next("end");
+        let second = labels.next("end");
+
+        assert_ne!(first, second);
+        assert!(first.starts_with(".L.end."));
+        assert!(second.starts_with(".L.end."));
+    }
+
+    #[test]
+    fn string_table_label() {
+        let mut strings = StringTable::new();
+        let mut labels = LabelGen::new();
+
+        let hello = strings.label(b"hello", &mut labels);
+        let world = strings.label(b"world", &mut labels);
+
+        assert_eq!(hello, strings.label(b"hello", &mut labels));
+        assert_ne!(hello, world);
+    }
 }
@@ -0,0 +1,384 @@
+use crate::{
+    codegen::{align_to, emit, emit_label, LabelGen, StringTable},
+    ctype::CType,
+    error::CompileError,
+    parser::{Node, NodeKind},
+};
+
+/// AArch64向けのコード生成器
+/// x86-64向けの`Codegen`と同じく、値はスタックに積んで計算する
+/// SPは16の倍数に保つ必要があるので、1つの値に16バイトを使う
+#[derive(Default)]
+pub struct Aarch64Codegen {
+    out: String,          // 出力するアセンブリ
+    labels: LabelGen,     // ラベルの生成器
+    strings: StringTable, // 文字列リテラルの表
+    return_label: String, // 生成中の関数から戻るときの飛び先
+}
+
+impl Aarch64Codegen {
+    pub fn new() -> Aarch64Codegen {
+        Aarch64Codegen::default()
+    }
+
+    /// 式全体を`main`関数の返り値とするプログラムを生成し、アセンブリを返す
+    pub fn gen_program(mut self, node: Node) -> Result<String, CompileError> {
+        // 局所変数はまだないので、スタックフレームに領域は要らない
+        self.gen_func("main", node, 0)?;
+
+        // 式中に現れた文字列リテラルを出力
+        self.strings.gen_rodata(&mut self.out);
+
+        Ok(self.out)
+    }
+
+    /// 関数のコードを生成する
+    /// `locals_size`は局所変数に必要なバイト数で、SPを16の倍数に保つように切り上げて確保する
+    pub fn gen_func(
+        &mut self,
+        name: &str,
+        body: Node,
+        locals_size: usize,
+    ) -> Result<(), CompileError> {
+        self.return_label = format!(".L.return.{}", name);
+
+        emit_label(&mut self.out, &format!(".globl {}", name));
+        emit_label(&mut self.out, &format!("{}:", name));
+
+        // プロローグ
+        // フレームポインタとリンクレジスタを退避する
+        emit(&mut self.out, "stp x29, x30, [sp, #-16]!");
+        emit(&mut self.out, "mov x29, sp");
+        emit(
+            &mut self.out,
+            &format!("sub sp, sp, #{}", align_to(locals_size, 16)),
+        );
+
+        self.gen_stmt(body)?;
+
+        // エピローグ
+        // 返り値はX0に入っている
+        emit_label(&mut self.out, &format!("{}:", self.return_label));
+        emit(&mut self.out, "mov sp, x29");
+        emit(&mut self.out, "ldp x29, x30, [sp], #16");
+        emit(&mut self.out, "ret");
+
+        Ok(())
+    }
+
+    /// 文のコードを生成する
+    /// 文はスタックに値を残さない
+    /// 今のところ文は式1つだけで、その値をX0に入れて関数からの返り値とする
+    pub fn gen_stmt(&mut self, node: Node) -> Result<(), CompileError> {
+        self.gen_expr(node)?;
+        self.pop("x0");
+
+        Ok(())
+    }
+
+    /// レジスタの値をスタックに積む
+    fn push(&mut self, reg: &str) {
+        emit(&mut self.out, &format!("str {}, [sp, #-16]!", reg));
+    }
+
+    /// スタックトップの値をレジスタに取り出す
+    fn pop(&mut self, reg: &str) {
+        emit(&mut self.out, &format!("ldr {}, [sp], #16", reg));
+    }
+
+    /// 左辺値のアドレスをスタックに積む
+    fn gen_lval(&mut self, node: Node) -> Result<(), CompileError> {
+        match node.get_kind() {
+            NodeKind::Deref => {
+                if let Some(lhs) = node.get_lhs() {
+                    self.gen_expr(*lhs)?;
+                }
+
+                Ok(())
+            }
+            _ => Err(CompileError::CodegenError {
+                message: "左辺値ではありません".to_string(),
+            }),
+        }
+    }
+
+    /// スタックトップのアドレスから値を読み込み、その値で置き換える
+    /// 配列は先頭のアドレスがそのまま値になるので、何も読み込まない
+    fn load(&mut self, ty: &CType) {
+        if let CType::Array(..) = ty {
+            return;
+        }
+
+        self.pop("x0");
+
+        match ty {
+            CType::Char => {
+                emit(&mut self.out, "ldrsb x0, [x0]");
+            }
+            CType::Short => {
+                emit(&mut self.out, "ldrsh x0, [x0]");
+            }
+            CType::UChar => {
+                emit(&mut self.out, "ldrb w0, [x0]");
+            }
+            CType::UShort => {
+                emit(&mut self.out, "ldrh w0, [x0]");
+            }
+            CType::Bool => {
+                emit(&mut self.out, "ldrb w0, [x0]");
+            }
+            _ => {
+                emit(&mut self.out, "ldr x0, [x0]");
+            }
+        }
+
+        self.push("x0");
+    }
+
+    /// 式のコードを生成する
+    /// 式の値はスタックに1つだけ積まれる
+    pub fn gen_expr(&mut self, node: Node) -> Result<(), CompileError> {
+        let node_kind = node.get_kind();
+
+        if let NodeKind::Addr = node_kind {
+            if let Some(lhs) = node.get_lhs() {
+                self.gen_lval(*lhs)?;
+            }
+            return Ok(());
+        }
+
+        if let NodeKind::Deref = node_kind {
+            let ty = node.get_type();
+
+            self.gen_lval(node)?;
+            self.load(&ty);
+            return Ok(());
+        }
+
+        if let NodeKind::Cast(ty) = node_kind {
+            if let Some(lhs) = node.get_lhs() {
+                self.gen_expr(*lhs)?;
+            }
+
+            // 値を変えるのは1バイトと2バイトの整数と_Boolへの変換だけ
+            // charは下位1バイト、shortは下位2バイトを符号拡張し、符号なしの型へはゼロ拡張する
+            // _Boolは0でなければ1にする
+            match ty {
+                CType::Char => {
+                    self.pop("x0");
+                    emit(&mut self.out, "sxtb x0, w0");
+                    self.push("x0");
+                }
+                CType::Short => {
+                    self.pop("x0");
+                    emit(&mut self.out, "sxth x0, w0");
+                    self.push("x0");
+                }
+                // 32ビットのレジスタに書き込むと上位32ビットはゼロになる
+                CType::UChar => {
+                    self.pop("x0");
+                    emit(&mut self.out, "uxtb w0, w0");
+                    self.push("x0");
+                }
+                CType::UShort => {
+                    self.pop("x0");
+                    emit(&mut self.out, "uxth w0, w0");
+                    self.push("x0");
+                }
+                CType::Bool => {
+                    self.pop("x0");
+                    emit(&mut self.out, "cmp x0, #0");
+                    emit(&mut self.out, "cset x0, ne");
+                    self.push("x0");
+                }
+                _ => {}
+            }
+
+            return Ok(());
+        }
+
+        if let NodeKind::Num(num) = node_kind {
+            // movの即値に収まらない数はリテラルプールから読み込む
+            if (-65536..=65535).contains(&num) {
+                emit(&mut self.out, &format!("mov x0, #{}", num));
+            } else {
+                emit(&mut self.out, &format!("ldr x0, ={}", num));
+            }
+
+            self.push("x0");
+            return Ok(());
+        }
+
+        if let NodeKind::Str(bytes) = node_kind {
+            let label = self.strings.label(&bytes, &mut self.labels);
+
+            emit(&mut self.out, &format!("adrp x0, {}", label));
+            emit(&mut self.out, &format!("add x0, x0, :lo12:{}", label));
+            self.push("x0");
+            return Ok(());
+        }
+
+        let unsigned = node.has_unsigned_operands();
+
+        if let Some(lhs) = node.get_lhs() {
+            self.gen_expr(*lhs)?;
+        };
+
+        if let Some(rhs) = node.get_rhs() {
+            self.gen_expr(*rhs)?;
+        };
+
+        self.pop("x1");
+        self.pop("x0");
+
+        let out = &mut self.out;
+
+        match node_kind {
+            NodeKind::Add => {
+                emit(out, "add x0, x0, x1");
+            }
+            NodeKind::Sub => {
+                emit(out, "sub x0, x0, x1");
+            }
+            NodeKind::Mul => {
+                emit(out, "mul x0, x0, x1");
+            }
+            NodeKind::Div if unsigned => {
+                emit(out, "udiv x0, x0, x1");
+            }
+            NodeKind::Div => {
+                emit(out, "sdiv x0, x0, x1");
+            }
+            NodeKind::Shl => {
+                emit(out, "lsl x0, x0, x1");
+            }
+            NodeKind::Eq => {
+                emit(out, "cmp x0, x1");
+                emit(out, "cset x0, eq");
+            }
+            NodeKind::Ne => {
+                emit(out, "cmp x0, x1");
+                emit(out, "cset x0, ne");
+            }
+            // 符号なしの比較では`lo`と`ls`を使う
+            NodeKind::Lt => {
+                let cond = if unsigned { "lo" } else { "lt" };
+
+                emit(out, "cmp x0, x1");
+                emit(out, &format!("cset x0, {}", cond));
+            }
+            NodeKind::Le => {
+                let cond = if unsigned { "ls" } else { "le" };
+
+                emit(out, "cmp x0, x1");
+                emit(out, &format!("cset x0, {}", cond));
+            }
+            _ => {
+                return Err(CompileError::CodegenError {
+                    message: "予期しないノードです".to_string(),
+                });
+            }
+        }
+
+        self.push("x0");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Aarch64Codegen;
+    use crate::{
+        lexer::Lexer,
+        parser::{Node, Parser},
+    };
+
+    fn parse(input: &str) -> Node {
+        let input = input.to_string();
+        let mut lexer = Lexer::new(&input);
+
+        lexer.tokenize().unwrap();
+
+        Parser::new(lexer).program().ok().unwrap()
+    }
+
+    #[test]
+    fn gen_program() {
+        let expected = "\
+.globl main
+main:
+  stp x29, x30, [sp, #-16]!
+  mov x29, sp
+  sub sp, sp, #0
+  mov x0, #1
+  str x0, [sp, #-16]!
+  mov x0, #2
+  str x0, [sp, #-16]!
+  ldr x1, [sp], #16
+  ldr x0, [sp], #16
+  cmp x0, x1
+  cset x0, lt
+  str x0, [sp, #-16]!
+  ldr x0, [sp], #16
+.L.return.main:
+  mov sp, x29
+  ldp x29, x30, [sp], #16
+  ret
+";
+
+        assert_eq!(
+            expected,
+            Aarch64Codegen::new().gen_program(parse("1<2")).unwrap()
+        );
+    }
+
+    #[test]
+    fn gen_large_number() {
+        let asm = Aarch64Codegen::new().gen_program(parse("1000000")).unwrap();
+
+        assert!(asm.contains("  ldr x0, =1000000\n"));
+    }
+
+    #[test]
+    fn gen_string_literal() {
+        let asm = Aarch64Codegen::new().gen_program(parse(r#"*"a""#)).unwrap();
+
+        assert!(asm.contains("  adrp x0, .L.str.0\n  add x0, x0, :lo12:.L.str.0\n"));
+        assert!(asm.contains("  ldrsb x0, [x0]\n"));
+        assert!(asm.ends_with(".section .rodata\n.L.str.0:\n  .byte 97, 0\n"));
+    }
+
+    #[test]
+    fn gen_short() {
+        let asm = Aarch64Codegen::new()
+            .gen_program(parse(r#"(short)65537 + *(short*)"ab""#))
+            .unwrap();
+
+        assert!(asm.contains("  sxth x0, w0\n"));
+        assert!(asm.contains("  ldrsh x0, [x0]\n"));
+    }
+
+    #[test]
+    fn gen_unsigned() {
+        let asm = Aarch64Codegen::new()
+            .gen_program(parse("(unsigned)-1 / 2 > (unsigned char)-1"))
+            .unwrap();
+
+        assert!(asm.contains("  udiv x0, x0, x1\n"));
+        assert!(asm.contains("  cset x0, lo\n"));
+        assert!(asm.contains("  uxtb w0, w0\n"));
+        assert!(!asm.contains("sdiv"));
+    }
+
+    #[test]
+    fn gen_array_deref() {
+        // 配列へのポインタを参照しても読み込まず、2回目の参照で要素を読み込む
+        let asm = Aarch64Codegen::new()
+            .gen_program(parse(r#"**(char(*)[3])"abc""#))
+            .unwrap();
+
+        assert_eq!(1, asm.matches("ldrsb").count());
+        assert!(!asm.contains("ldr x0, [x0]"));
+    }
+}
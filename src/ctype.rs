@@ -0,0 +1,162 @@
+use std::fmt;
+
+/// C言語の型
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CType {
+    Int,
+    Char,
+    Short,
+    Long,
+    UChar, // 符号なしの整数
+    UShort,
+    UInt,
+    ULong,
+    Bool, // 0か1だけを持つ1バイトの整数
+    Ptr(Box<CType>),
+    Array(Box<CType>, usize), // 要素の型と要素数
+}
+
+impl CType {
+    /// 型のサイズ(バイト数)
+    pub fn size(&self) -> isize {
+        match self {
+            CType::Int | CType::UInt => 8,
+            CType::Char | CType::UChar => 1,
+            CType::Short | CType::UShort => 2,
+            CType::Long | CType::ULong => 8,
+            CType::Bool => 1,
+            CType::Ptr(_) => 8,
+            CType::Array(base, len) => CType::array_size(base, *len)
+                .expect("配列のサイズは型を組み立てるときに検査している"),
+        }
+    }
+
+    /// 符号なしの整数型かどうか
+    pub fn is_unsigned(&self) -> bool {
+        matches!(
+            self,
+            CType::UChar | CType::UShort | CType::UInt | CType::ULong
+        )
+    }
+
+    /// 同じ大きさの符号なしの整数型
+    /// 符号の区別のない型はそのまま返す
+    pub fn to_unsigned(self) -> CType {
+        match self {
+            CType::Char => CType::UChar,
+            CType::Short => CType::UShort,
+            CType::Int => CType::UInt,
+            CType::Long => CType::ULong,
+            ty => ty,
+        }
+    }
+
+    /// 二項演算の被演算子をそろえる型(通常の算術型変換)
+    /// intより小さい型はintに格上げする
+    /// intとlongは同じ8バイトなので、どちらかが符号なしなら符号なしの大きいほうの型になる
+    pub fn usual_arith_conv(lhs: &CType, rhs: &CType) -> CType {
+        let promote = |ty: &CType| match ty {
+            CType::Long | CType::UInt | CType::ULong => ty.clone(),
+            _ => CType::Int,
+        };
+        let (lhs, rhs) = (promote(lhs), promote(rhs));
+        let is_long =
+            lhs == CType::Long || lhs == CType::ULong || rhs == CType::Long || rhs == CType::ULong;
+
+        match (lhs.is_unsigned() || rhs.is_unsigned(), is_long) {
+            (true, true) => CType::ULong,
+            (true, false) => CType::UInt,
+            (false, true) => CType::Long,
+            (false, false) => CType::Int,
+        }
+    }
+
+    /// 要素の型が`base`で要素数が`len`の配列のサイズ
+    /// isizeに収まらない場合はNoneを返す
+    pub fn array_size(base: &CType, len: usize) -> Option<isize> {
+        isize::try_from(len).ok()?.checked_mul(base.size())
+    }
+
+    /// ポインタ型ならその指す先の型を、配列型なら要素の型を返す
+    pub fn base(&self) -> Option<CType> {
+        match self {
+            CType::Ptr(base) | CType::Array(base, _) => Some(*base.clone()),
+            _ => None,
+        }
+    }
+
+    /// 式の中で使われたときの型
+    /// 配列は先頭の要素を指すポインタに読み替える
+    pub fn decay(self) -> CType {
+        match self {
+            CType::Array(base, _) => CType::Ptr(base),
+            ty => ty,
+        }
+    }
+}
+
+/// `int`や`char*`、`int[2][3]`のようにC言語の型名に近い形で表す
+impl fmt::Display for CType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CType::Int => write!(f, "int"),
+            CType::Char => write!(f, "char"),
+            CType::Short => write!(f, "short"),
+            CType::Long => write!(f, "long"),
+            CType::UChar => write!(f, "unsigned char"),
+            CType::UShort => write!(f, "unsigned short"),
+            CType::UInt => write!(f, "unsigned int"),
+            CType::ULong => write!(f, "unsigned long"),
+            CType::Bool => write!(f, "_Bool"),
+            CType::Ptr(base) => write!(f, "{}*", base),
+            CType::Array(base, len) => {
+                // 多次元配列は外側の要素数から順に並べる
+                let mut dims = vec![*len];
+                let mut elem = base.as_ref();
+
+                while let CType::Array(base, len) = elem {
+                    dims.push(*len);
+                    elem = base;
+                }
+
+                write!(f, "{}", elem)?;
+
+                for len in dims {
+                    write!(f, "[{}]", len)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CType;
+
+    #[test]
+    fn usual_arith_conv() {
+        let cases = [
+            (CType::Char, CType::Short, CType::Int),
+            (CType::UChar, CType::Int, CType::Int),
+            (CType::Int, CType::Long, CType::Long),
+            (CType::UInt, CType::Int, CType::UInt),
+            (CType::UInt, CType::Long, CType::ULong),
+            (CType::ULong, CType::Char, CType::ULong),
+            (CType::UShort, CType::UShort, CType::Int),
+        ];
+
+        for (lhs, rhs, expected) in cases {
+            assert_eq!(expected, CType::usual_arith_conv(&lhs, &rhs));
+            assert_eq!(expected, CType::usual_arith_conv(&rhs, &lhs));
+        }
+    }
+
+    #[test]
+    fn size_ignores_signedness() {
+        for ty in [CType::Char, CType::Short, CType::Int, CType::Long] {
+            assert_eq!(ty.size(), ty.clone().to_unsigned().size());
+        }
+    }
+}
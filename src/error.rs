@@ -0,0 +1,72 @@
+use std::fmt;
+
+/// 入力プログラム中の範囲(バイト単位)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize, // 範囲の先頭の位置
+    pub end: usize,   // 範囲の末尾の次の位置
+}
+
+impl Span {
+    /// 1つの位置だけを指す範囲
+    pub fn at(pos: usize) -> Span {
+        Span {
+            start: pos,
+            end: pos,
+        }
+    }
+}
+
+/// コンパイルエラー
+/// どの段階で起きたエラーかを種類で区別する
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    LexError { span: Span, message: String }, // 字句解析のエラー
+    ParseError { span: Span, message: String }, // 構文解析のエラー
+    CodegenError { message: String },         // コード生成のエラー
+}
+
+impl CompileError {
+    /// 入力プログラム中の`pos`で起きた字句解析のエラー
+    pub fn lex(pos: usize, message: impl Into<String>) -> CompileError {
+        CompileError::LexError {
+            span: Span::at(pos),
+            message: message.into(),
+        }
+    }
+
+    /// 入力プログラム中の`pos`で起きた構文解析のエラー
+    pub fn parse(pos: usize, message: impl Into<String>) -> CompileError {
+        CompileError::ParseError {
+            span: Span::at(pos),
+            message: message.into(),
+        }
+    }
+
+    /// エラーの原因となった入力プログラム中の範囲
+    /// コード生成のエラーは入力のどこかを指すものではないのでNoneを返す
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            CompileError::LexError { span, .. } | CompileError::ParseError { span, .. } => {
+                Some(*span)
+            }
+            CompileError::CodegenError { .. } => None,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            CompileError::LexError { message, .. }
+            | CompileError::ParseError { message, .. }
+            | CompileError::CodegenError { message } => message,
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl std::error::Error for CompileError {}
@@ -1,19 +1,34 @@
-use std::{fmt, iter::Peekable, str::Chars, vec::IntoIter};
+use std::{fmt, num::IntErrorKind, str::Chars, vec::IntoIter};
+
+use crate::error::CompileError;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Reserved {
     LeftParen,
     RightParen,
+    LeftBracket,
+    RightBracket,
     Plus,
     Minus,
+    Decrement,
     Asterisk,
     Slash,
+    Ampersand,
     Eq,
     Gt,
     Ge,
     Le,
     Lt,
     Ne,
+    Int,
+    Char,
+    Short,
+    Long,
+    Signed,
+    Unsigned,
+    Bool,
+    Const,
+    SizeOf,
 }
 
 impl fmt::Display for Reserved {
@@ -21,16 +36,29 @@ impl fmt::Display for Reserved {
         let s = match self {
             Reserved::LeftParen => "(",
             Reserved::RightParen => ")",
+            Reserved::LeftBracket => "[",
+            Reserved::RightBracket => "]",
             Reserved::Plus => "+",
             Reserved::Minus => "-",
+            Reserved::Decrement => "--",
             Reserved::Asterisk => "*",
             Reserved::Slash => "/",
+            Reserved::Ampersand => "&",
             Reserved::Eq => "==",
             Reserved::Gt => ">",
             Reserved::Ge => ">=",
             Reserved::Le => "<=",
             Reserved::Lt => "<",
             Reserved::Ne => "!=",
+            Reserved::Int => "int",
+            Reserved::Char => "char",
+            Reserved::Short => "short",
+            Reserved::Long => "long",
+            Reserved::Signed => "signed",
+            Reserved::Unsigned => "unsigned",
+            Reserved::Bool => "_Bool",
+            Reserved::Const => "const",
+            Reserved::SizeOf => "sizeof",
         };
 
         write!(f, "{}", s)
@@ -39,11 +67,12 @@ impl fmt::Display for Reserved {
 
 impl Reserved {
     /// 記号の長さ
-    pub fn len(&self) -> usize {
+    pub(crate) fn len(&self) -> usize {
         self.to_string().len()
     }
 }
 
+#[allow(dead_code)]
 pub struct ReservedError(char);
 
 impl TryFrom<&char> for Reserved {
@@ -53,10 +82,13 @@ impl TryFrom<&char> for Reserved {
         match item {
             '(' => Ok(Reserved::LeftParen),
             ')' => Ok(Reserved::RightParen),
+            '[' => Ok(Reserved::LeftBracket),
+            ']' => Ok(Reserved::RightBracket),
             '+' => Ok(Reserved::Plus),
             '-' => Ok(Reserved::Minus),
             '*' => Ok(Reserved::Asterisk),
             '/' => Ok(Reserved::Slash),
+            '&' => Ok(Reserved::Ampersand),
             _ => Err(ReservedError(*item)),
         }
     }
@@ -66,34 +98,97 @@ impl TryFrom<&char> for Reserved {
 pub enum TokenKind {
     Reserved(Reserved), // 記号
     Num(isize),         // 整数とその値
-    EOF,                // 入力の終わりを表すトークン
+    Str(Vec<u8>),       // 文字列リテラルとそのバイト列
+    Eof,                // 入力の終わりを表すトークン
+}
+
+impl TokenKind {
+    /// トークンの種類の名前
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenKind::Reserved(_) => "Reserved",
+            TokenKind::Num(_) => "Num",
+            TokenKind::Str(_) => "Str",
+            TokenKind::Eof => "Eof",
+        }
+    }
 }
 
 #[derive(Clone)]
-pub struct Token<'a> {
-    kind: TokenKind,            // トークンの型
-    chars: Peekable<Chars<'a>>, // そのトークン以降の文字列
+pub struct Token {
+    kind: TokenKind, // トークンの型
+    pos: usize,      // 入力プログラム中のトークンの位置(バイト単位)
+    lexeme: String,  // 入力プログラム中のトークンの文字列
+}
+
+impl Token {
+    fn new(kind: TokenKind, pos: usize, lexeme: &str) -> Token {
+        Token {
+            kind,
+            pos,
+            lexeme: lexeme.to_string(),
+        }
+    }
+
+    pub fn get_kind(&self) -> TokenKind {
+        self.kind.clone()
+    }
+
+    pub fn get_pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn get_lexeme(&self) -> &str {
+        &self.lexeme
+    }
+
+    /// トークンの種類、文字列、入力プログラム中の範囲をJSONのオブジェクトにする
+    /// 範囲は`[先頭, 末尾の次]`のバイト位置で表す
+    pub fn to_json(&self) -> String {
+        format!(
+            r#"{{"kind":"{}","lexeme":{},"span":[{},{}]}}"#,
+            self.kind.name(),
+            json_string(&self.lexeme),
+            self.pos,
+            self.pos + self.lexeme.len()
+        )
+    }
 }
 
-impl<'a> Token<'a> {
-    fn new(kind: TokenKind, chars: Peekable<Chars<'a>>) -> Token<'a> {
-        Token { kind, chars }
+/// 文字列を引用符で囲み、JSONの文字列としてエスケープする
+fn json_string(s: &str) -> String {
+    let mut result = String::from('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            '\r' => result.push_str("\\r"),
+            c if c.is_control() => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
     }
+
+    result.push('"');
+
+    result
 }
 
 #[derive(Clone)]
 pub struct Lexer<'a> {
-    input: &'a String, // 入力プログラム
-    chars: Peekable<Chars<'a>>,
-    tokens: Peekable<IntoIter<Token<'a>>>,
+    input: &'a String,       // 入力プログラム
+    chars: Chars<'a>,        // まだ読んでいない部分の文字列
+    tokens: IntoIter<Token>, // まだ読んでいないトークン
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a String) -> Lexer<'a> {
         Lexer {
             input,
-            chars: input.chars().peekable(),
-            tokens: vec![].into_iter().peekable(),
+            chars: input.chars(),
+            tokens: vec![].into_iter(),
         }
     }
 
@@ -101,35 +196,60 @@ impl<'a> Lexer<'a> {
         self.input.clone()
     }
 
-    pub fn get_chars(&self) -> Peekable<Chars<'a>> {
+    pub fn get_chars(&self) -> Chars<'a> {
         self.chars.clone()
     }
 
-    pub fn get_tokens(&self) -> Peekable<IntoIter<Token<'a>>> {
+    pub fn get_tokens(&self) -> IntoIter<Token> {
         self.tokens.clone()
     }
 
-    pub fn tokenize(&mut self) -> Result<Vec<Token<'a>>, impl fmt::Display> {
-        let mut result: Vec<Token<'a>> = Vec::new();
+    /// 次の文字を読み進めずに返す
+    fn peek(&self) -> Option<char> {
+        self.chars.clone().next()
+    }
+
+    /// 次に読む文字の入力プログラム中の位置(バイト単位)
+    fn pos(&self) -> usize {
+        self.input.len() - self.chars.as_str().len()
+    }
+
+    /// `pos`から次に読む文字の手前までを文字列とするトークンを作る
+    fn token(&self, kind: TokenKind, pos: usize) -> Token {
+        Token::new(kind, pos, &self.input[pos..self.pos()])
+    }
+
+    /// 次に読む文字の位置で起きた字句解析のエラーを作る
+    fn error(&self, msg: impl Into<String>) -> CompileError {
+        CompileError::lex(self.pos(), msg)
+    }
+
+    pub fn tokenize(&mut self) -> Result<Vec<Token>, CompileError> {
+        let mut result: Vec<Token> = Vec::new();
+
+        while let Some(c) = self.peek() {
+            let pos = self.pos();
 
-        while let Some(c) = self.chars.clone().peek() {
             match c {
                 c if c.is_whitespace() => {
                     self.chars.next();
                 }
-                '(' | ')' | '+' | '-' | '*' | '/' => {
-                    let reserved = Reserved::try_from(c);
+                // 最長一致で読むので、`--`は常にデクリメントになる
+                // 符号の反転を重ねるには`- -`と空白を挟む
+                '-' if self.start_with("--") => {
+                    self.chars.nth(1);
+                    result.push(self.token(TokenKind::Reserved(Reserved::Decrement), pos));
+                }
+                '(' | ')' | '[' | ']' | '+' | '-' | '*' | '/' | '&' => {
+                    let reserved = Reserved::try_from(&c);
 
                     match reserved {
                         Ok(reserved) => {
-                            let token =
-                                Token::new(TokenKind::Reserved(reserved), self.chars.clone());
-
                             self.chars.next();
-                            result.push(token);
+                            result.push(self.token(TokenKind::Reserved(reserved), pos));
                         }
                         Err(_) => {
-                            return Err("予期しない文字です");
+                            return Err(self.error("予期しない文字です"));
                         }
                     }
                 }
@@ -147,63 +267,127 @@ impl<'a> Lexer<'a> {
                     } else if self.start_with(">") {
                         Reserved::Gt
                     } else {
-                        return Err("予期しない文字です");
+                        return Err(self.error("予期しない文字です"));
                     };
-                    let reserved_len = reserved.len();
-                    let token = Token::new(TokenKind::Reserved(reserved), self.chars.clone());
-
-                    for _ in 0..reserved_len {
+                    for _ in 0..reserved.len() {
                         self.chars.next();
                     }
 
-                    result.push(token);
+                    result.push(self.token(TokenKind::Reserved(reserved), pos));
+                }
+                '\'' => {
+                    let is_escape = self.start_with("'\\");
+                    let c = self.take_char_literal()?;
+                    // エスケープシーケンスは1バイトの値を表すので、charを読み込んだときと同じく符号拡張する
+                    let value = if is_escape {
+                        c as u8 as i8 as isize
+                    } else {
+                        c as isize
+                    };
+
+                    result.push(self.token(TokenKind::Num(value), pos));
+                }
+                '"' => {
+                    let bytes = self.take_string_literal()?;
+
+                    result.push(self.token(TokenKind::Str(bytes), pos));
+                }
+                // 数字の前に置かれたアンダースコアは、識別子ではなく数字の区切りの誤りとして報告する
+                '_' if self
+                    .chars
+                    .clone()
+                    .nth(1)
+                    .is_some_and(|c| c.is_ascii_digit()) =>
+                {
+                    return Err(self.error("数字の区切りの `_` は数字の間にしか置けません"));
+                }
+                c if c.is_ascii_alphabetic() || c == '_' => {
+                    let token_chars = self.chars.clone();
+                    let ident = self.take_ident();
+                    let reserved = match ident.as_str() {
+                        "int" => Reserved::Int,
+                        "char" => Reserved::Char,
+                        "short" => Reserved::Short,
+                        "long" => Reserved::Long,
+                        "signed" => Reserved::Signed,
+                        "unsigned" => Reserved::Unsigned,
+                        "_Bool" => Reserved::Bool,
+                        "const" => Reserved::Const,
+                        "sizeof" => Reserved::SizeOf,
+                        _ => {
+                            // 識別子の先頭でエラーを報告する
+                            self.chars = token_chars;
+
+                            return Err(self.error("予期しない識別子です"));
+                        }
+                    };
+
+                    result.push(self.token(TokenKind::Reserved(reserved), pos));
                 }
                 c if c.is_numeric() => {
+                    let start = self.chars.clone();
                     let num = match self.take_num_str() {
                         Ok(s) => s,
+                        // `01`のように0の後ろに数字が続く場合は、数字ではない文字とは分けて数値の先頭で報告する
+                        Err((s, c)) if s == "0" && c.is_ascii_digit() => {
+                            self.chars = start;
+
+                            return Err(self.error("先頭に 0 を置いた数値はサポートされていません"));
+                        }
+                        // 数値に英数字やアンダースコアが続く場合は、読み込めた部分と不正な文字をその位置で示す
+                        // 区切りとして使えない位置のアンダースコアもここでエラーになる
+                        Err((s, c)) if c.is_alphanumeric() || c == '_' => {
+                            return Err(self
+                                .error(format!("`{}` の後ろの `{}` は数字ではありません", s, c)));
+                        }
                         Err((s, _)) => s,
                     };
 
-                    if let Ok(num) = num.parse::<isize>() {
-                        let token = Token::new(TokenKind::Num(num), self.chars.clone());
+                    match Lexer::check_numeric_range(&num) {
+                        Ok(num) => {
+                            result.push(self.token(TokenKind::Num(num), pos));
+                        }
+                        // 範囲外の数は数値の先頭でエラーにする
+                        Err(msg) => {
+                            self.chars = start;
 
-                        result.push(token);
-                    } else {
-                        return Err("数ではありません");
+                            return Err(self.error(msg));
+                        }
                     }
                 }
                 _ => {
-                    return Err("トークナイズできません");
+                    return Err(self.error("トークナイズできません"));
                 }
             }
         }
 
-        result.push(Token::new(TokenKind::EOF, self.chars.clone()));
+        result.push(self.token(TokenKind::Eof, self.pos()));
 
         // トークンを保存
-        self.tokens = result.clone().into_iter().peekable();
+        self.tokens = result.clone().into_iter();
 
         Ok(result)
     }
 
     /// 数値であるような文字列全体もしくは先頭から続く部分列を取り出す
     /// 0から始まる数字の羅列や、数字以外が含まれる場合は、途中まで読み込んだ数値と不正な文字をタプルとしてErrで返す
+    /// 数字の間にある`_`は読みやすさのための区切りとして読み飛ばす
     /// 備考: C言語のstrtolの仕様を参考にした
     pub fn take_num_str(&mut self) -> Result<String, (String, char)> {
         let mut result = String::new();
 
-        while let Some(c) = self.chars.peek() {
+        while let Some(c) = self.peek() {
             match c {
                 // 先頭の空白は無視する
-                c if result.len() == 0 && c.is_whitespace() => {
+                c if result.is_empty() && c.is_whitespace() => {
                     self.chars.next();
                 }
 
                 // 符号付き整数の可能性がある
                 '+' | '-' => {
                     // 符号の位置が先頭なら、文字列全体もしくは先頭から続く部分列が整数である可能性がある
-                    if result.len() == 0 {
-                        let op = *c;
+                    if result.is_empty() {
+                        let op = c;
 
                         // イテレータの2番目の要素が数字かどうか調べる
                         if let Some(c) = self.chars.clone().nth(1) {
@@ -226,27 +410,38 @@ impl<'a> Lexer<'a> {
                             return Err((result, op));
                         }
                     } else {
-                        return Err((result, *c));
+                        return Err((result, c));
                     }
                 }
 
                 c if c.is_numeric() => {
-                    if result.len() == 0 && *c == '0' {
+                    if result.is_empty() && c == '0' {
                         self.chars.next();
 
-                        if let Some(next_char) = self.chars.peek() {
-                            return Err(("0".to_string(), *next_char));
+                        if let Some(next_char) = self.peek() {
+                            return Err(("0".to_string(), next_char));
                         } else {
                             return Ok("0".to_string());
                         }
                     }
 
-                    result.push(*c);
+                    result.push(c);
+                    self.chars.next();
+                }
+
+                // 数字に挟まれたアンダースコアは区切りとして読み飛ばす
+                '_' if result.ends_with(|c: char| c.is_ascii_digit())
+                    && self
+                        .chars
+                        .clone()
+                        .nth(1)
+                        .is_some_and(|c| c.is_ascii_digit()) =>
+                {
                     self.chars.next();
                 }
 
                 c => {
-                    return Err((result, *c));
+                    return Err((result, c));
                 }
             }
         }
@@ -254,40 +449,199 @@ impl<'a> Lexer<'a> {
         Ok(result)
     }
 
-    /// 与えられた文字列から始まるかどうかを判定する
-    /// 元のイテレータは読み進めない
-    pub fn start_with(&self, s: &'static str) -> bool {
-        let mut target = self.chars.clone().take(s.len());
-        let mut input = s.chars();
+    /// `take_num_str`で取り出した数字の列を数値に変換する
+    /// isizeに収まらない場合は、その数字の列を添えたエラーメッセージを返す
+    pub fn check_numeric_range(s: &str) -> Result<isize, String> {
+        s.parse::<isize>().map_err(|err| match err.kind() {
+            IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+                format!("整数オーバーフローです: {}", s)
+            }
+            _ => "数ではありません".to_string(),
+        })
+    }
 
-        while let Some(c_target) = target.next() {
-            if let Some(c_input) = input.next() {
-                if c_target != c_input {
-                    return false;
+    /// `'a'`や`'\n'`のような文字リテラルを読み込み、その文字を返す
+    /// 開きクォートから閉じクォートまでを読み進める
+    /// エラーの場合はその原因となった位置まで戻る
+    pub fn take_char_literal(&mut self) -> Result<char, CompileError> {
+        let start = self.chars.clone();
+
+        // 開きクォートを読み飛ばす
+        self.chars.next();
+
+        let escape = self.chars.clone();
+        let c = match self.chars.next() {
+            Some('\\') => match Lexer::process_escape(&mut self.chars) {
+                Ok(c) => c,
+                Err(msg) => {
+                    self.chars = escape;
+
+                    return Err(self.error(msg));
                 }
+            },
+            Some('\'') => {
+                self.chars = start;
+
+                return Err(self.error("空の文字リテラルです"));
             }
-        }
+            Some(c) => c,
+            None => {
+                self.chars = start;
 
-        match input.next() {
-            Some(_) => {
-                return false;
+                return Err(self.error("文字リテラルが閉じられていません"));
+            }
+        };
+
+        let msg = match self.chars.next() {
+            Some('\'') => {
+                return Ok(c);
+            }
+            // 後ろに閉じクォートがあれば複数の文字が書かれている
+            Some(_) if self.chars.clone().any(|c| c == '\'') => {
+                "文字リテラルに複数の文字が含まれています"
             }
+            _ => "文字リテラルが閉じられていません",
+        };
+
+        self.chars = start;
+
+        Err(self.error(msg))
+    }
+
+    /// `"abc"`のような文字列リテラルを読み込み、そのバイト列を返す
+    /// 開きクォートから閉じクォートまでを読み進める
+    /// エラーの場合はその原因となった位置まで戻る
+    pub fn take_string_literal(&mut self) -> Result<Vec<u8>, CompileError> {
+        let mut result = Vec::new();
+        let start = self.chars.clone();
+
+        // 開きクォートを読み飛ばす
+        self.chars.next();
+
+        loop {
+            let escape = self.chars.clone();
+
+            match self.chars.next() {
+                Some('"') => {
+                    return Ok(result);
+                }
+                Some('\\') => match Lexer::process_escape(&mut self.chars) {
+                    // エスケープシーケンスは1バイトの値を表す
+                    Ok(c) => {
+                        result.push(c as u8);
+                    }
+                    Err(msg) => {
+                        self.chars = escape;
+
+                        return Err(self.error(msg));
+                    }
+                },
+                Some(c) => {
+                    result.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                None => {
+                    self.chars = start;
+
+                    return Err(self.error("文字列リテラルが閉じられていません"));
+                }
+            }
+        }
+    }
+
+    /// バックスラッシュに続くエスケープシーケンスを読み込み、それが表す文字を返す
+    /// 文字リテラルと文字列リテラルで共通して使う
+    /// `\xHH`と`\OOO`は1バイトの値を表すので、返す文字は0から255の範囲に収まる
+    pub fn process_escape(chars: &mut Chars) -> Result<char, String> {
+        let c = match chars.next() {
+            Some(c) => c,
             None => {
-                return true;
+                return Err("エスケープシーケンスが途中で終わっています".to_string());
+            }
+        };
+
+        match c {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            'a' => Ok('\u{7}'),
+            '\\' => Ok('\\'),
+            '"' => Ok('"'),
+            '\'' => Ok('\''),
+            'x' => {
+                let mut value = 0;
+                let mut len = 0;
+
+                // 16進数は2桁まで読む
+                while let Some(digit) = chars.clone().next().and_then(|c| c.to_digit(16)) {
+                    if len == 2 {
+                        break;
+                    }
+
+                    value = value * 16 + digit;
+                    len += 1;
+                    chars.next();
+                }
+
+                if len == 0 {
+                    return Err("\\xの後に16進数がありません".to_string());
+                }
+
+                Ok(char::from(value as u8))
             }
+            '0'..='7' => {
+                let mut value = c.to_digit(8).unwrap_or(0);
+                let mut len = 1;
+
+                // 8進数は3桁まで読む
+                while let Some(digit) = chars.clone().next().and_then(|c| c.to_digit(8)) {
+                    if len == 3 {
+                        break;
+                    }
+
+                    value = value * 8 + digit;
+                    len += 1;
+                    chars.next();
+                }
+
+                match u8::try_from(value) {
+                    Ok(value) => Ok(char::from(value)),
+                    Err(_) => Err("8進数のエスケープシーケンスが1バイトに収まりません".to_string()),
+                }
+            }
+            c => Err(format!("不明なエスケープシーケンス`\\{}`です", c)),
         }
     }
 
-    pub fn at_eof(&mut self) -> bool {
-        if let Some(Token {
-            kind: TokenKind::EOF,
-            ..
-        }) = self.tokens.peek()
-        {
-            true
-        } else {
-            false
+    /// 英字またはアンダースコアから始まり、英数字またはアンダースコアが続く文字列を取り出す
+    pub fn take_ident(&mut self) -> String {
+        let mut result = String::new();
+
+        while let Some(c) = self.peek() {
+            if !(c.is_ascii_alphanumeric() || c == '_') {
+                break;
+            }
+
+            result.push(c);
+            self.chars.next();
         }
+
+        result
+    }
+
+    /// まだ読んでいない入力が与えられた文字列から始まるかどうかを判定する
+    /// 元のイテレータは読み進めず、入力が先に尽きた場合は偽を返す
+    pub fn start_with(&self, s: &'static str) -> bool {
+        self.chars.as_str().starts_with(s)
+    }
+
+    pub fn at_eof(&mut self) -> bool {
+        matches!(
+            self.tokens.as_slice().first(),
+            Some(Token {
+                kind: TokenKind::Eof,
+                ..
+            })
+        )
     }
 
     /// 次のトークンが期待している記号の時は、トークンを1つ読み進めて真を返す
@@ -296,7 +650,7 @@ impl<'a> Lexer<'a> {
         if let Some(Token {
             kind: TokenKind::Reserved(reserved),
             ..
-        }) = self.tokens.peek()
+        }) = self.tokens.as_slice().first()
         {
             if *reserved == expect {
                 self.tokens.next();
@@ -308,13 +662,55 @@ impl<'a> Lexer<'a> {
         false
     }
 
+    /// 次のトークンの入力プログラム中の位置を返す
+    /// トークンが残っていなければ入力の終わりの位置を返す
+    pub fn peek_pos(&mut self) -> usize {
+        match self.tokens.as_slice().first() {
+            Some(token) => token.pos,
+            None => self.input.len(),
+        }
+    }
+
+    /// 次のトークンの入力プログラム中の文字列を返す
+    pub fn peek_lexeme(&mut self) -> &str {
+        match self.tokens.as_slice().first() {
+            Some(token) => &token.lexeme,
+            None => "",
+        }
+    }
+
+    /// 次のトークンが記号の時は、トークンを読み進めずにその記号を返す
+    pub fn peek_reserved(&mut self) -> Option<Reserved> {
+        if let Some(Token {
+            kind: TokenKind::Reserved(reserved),
+            ..
+        }) = self.tokens.as_slice().first()
+        {
+            return Some(reserved.clone());
+        }
+
+        None
+    }
+
+    /// `n`個先のトークンが記号の時は、トークンを読み進めずにその記号を返す
+    /// トークン列を複製せずに先読みできるので、`peek_nth(0)`は`peek_reserved`と同じになる
+    pub fn peek_nth(&self, n: usize) -> Option<Reserved> {
+        match self.tokens.as_slice().get(n) {
+            Some(Token {
+                kind: TokenKind::Reserved(reserved),
+                ..
+            }) => Some(reserved.clone()),
+            _ => None,
+        }
+    }
+
     /// 次のトークンが期待している記号の時は、トークンを1つ読み進める
     /// それ以外の場合はエラーを報告する
-    pub fn expect(&mut self, expect: Reserved) -> Result<(), String> {
+    pub fn expect(&mut self, expect: Reserved) -> Result<(), CompileError> {
         if let Some(Token {
             kind: TokenKind::Reserved(reserved),
             ..
-        }) = self.tokens.peek()
+        }) = self.tokens.as_slice().first()
         {
             if *reserved == expect {
                 self.tokens.next();
@@ -323,16 +719,19 @@ impl<'a> Lexer<'a> {
             }
         }
 
-        Err(format!("{}ではありません", expect))
+        Err(CompileError::parse(
+            self.peek_pos(),
+            format!("{}ではありません", expect),
+        ))
     }
 
     /// 次のトークンが数値の場合、トークンを1つ読み進めてその数値を返す。
     /// それ以外の場合にはエラーを報告する。
-    pub fn expect_number(&mut self) -> Result<isize, String> {
+    pub fn expect_number(&mut self) -> Result<isize, CompileError> {
         if let Some(Token {
             kind: TokenKind::Num(num),
             ..
-        }) = self.tokens.peek()
+        }) = self.tokens.as_slice().first()
         {
             let num = *num;
 
@@ -341,33 +740,53 @@ impl<'a> Lexer<'a> {
             return Ok(num);
         }
 
-        Err("数ではありません".to_string())
+        Err(CompileError::parse(self.peek_pos(), "数ではありません"))
     }
 
-    /// 発生したエラー箇所を報告する
-    pub fn error_at(&mut self, msg: impl fmt::Display) -> String {
-        // トークナイズ中かトークンの消費中かを判別する
-        let is_tokenizing = self.chars.peek() != None;
-        let input = self.input.clone();
-        // tokensが空なら元のプログラムの最後の位置でエラーを報告する
-        let pos = if is_tokenizing {
-            input.len() - self.chars.clone().count()
-        } else {
-            if let Some(token) = self.tokens.peek() {
-                input.len() - token.chars.clone().count()
-            } else {
-                input.len()
-            }
-        };
-        let msg_with_arrow = format!("{}^ {}", " ".repeat(pos), msg);
+    /// 次のトークンが文字列リテラルの場合、トークンを1つ読み進めてそのバイト列を返す。
+    /// それ以外の場合にはエラーを報告する。
+    pub fn expect_string(&mut self) -> Result<Vec<u8>, CompileError> {
+        if let Some(Token {
+            kind: TokenKind::Str(bytes),
+            ..
+        }) = self.tokens.as_slice().first()
+        {
+            let bytes = bytes.clone();
 
-        return format!("{}\n{}", input, msg_with_arrow);
+            self.tokens.next();
+
+            return Ok(bytes);
+        }
+
+        Err(CompileError::parse(
+            self.peek_pos(),
+            "文字列リテラルではありません",
+        ))
+    }
+
+    /// コンパイルエラーを、入力プログラムとエラー箇所を指す`^`を添えて報告する
+    /// 入力のどこかを指すものでないエラーはメッセージだけを返す
+    pub fn error_at(&self, err: &CompileError) -> String {
+        match err.span() {
+            Some(span) => self.error_at_pos(span.start, err),
+            None => err.to_string(),
+        }
+    }
+
+    /// 入力プログラム中の指定された位置(バイト単位)でエラーを報告する
+    pub fn error_at_pos(&self, pos: usize, msg: impl fmt::Display) -> String {
+        // マルチバイト文字があってもずれないように、バイト数ではなく文字数で位置を数える
+        let column = self.input[..pos].chars().count();
+        let msg_with_arrow = format!("{}^ {}", " ".repeat(column), msg);
+
+        format!("{}\n{}", self.input, msg_with_arrow)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::Lexer;
+    use super::{Lexer, Reserved, TokenKind};
+    use crate::error::CompileError;
 
     #[test]
     fn take_num_str() {
@@ -419,6 +838,480 @@ mod test {
 
             assert_eq!(("5".to_string(), '+'), lexer.take_num_str().unwrap_err());
         }
+
+        {
+            let input = "1_000_000".to_string();
+            let mut lexer = Lexer::new(&input);
+
+            assert_eq!("1000000", lexer.take_num_str().unwrap());
+        }
+
+        {
+            let input = "-12_3".to_string();
+            let mut lexer = Lexer::new(&input);
+
+            assert_eq!("-123", lexer.take_num_str().unwrap());
+        }
+
+        {
+            let input = "1__0".to_string();
+            let mut lexer = Lexer::new(&input);
+
+            assert_eq!(("1".to_string(), '_'), lexer.take_num_str().unwrap_err());
+        }
+
+        {
+            let input = "10_".to_string();
+            let mut lexer = Lexer::new(&input);
+
+            assert_eq!(("10".to_string(), '_'), lexer.take_num_str().unwrap_err());
+        }
+
+        {
+            let input = "_1".to_string();
+            let mut lexer = Lexer::new(&input);
+
+            assert_eq!(("".to_string(), '_'), lexer.take_num_str().unwrap_err());
+        }
+    }
+
+    #[test]
+    fn take_char_literal() {
+        {
+            let input = "'A'".to_string();
+            let mut lexer = Lexer::new(&input);
+
+            assert_eq!('A', lexer.take_char_literal().unwrap());
+        }
+
+        {
+            let input = r"'\n'".to_string();
+            let mut lexer = Lexer::new(&input);
+
+            assert_eq!('\n', lexer.take_char_literal().unwrap());
+        }
+
+        {
+            let input = r"'\''".to_string();
+            let mut lexer = Lexer::new(&input);
+
+            assert_eq!('\'', lexer.take_char_literal().unwrap());
+        }
+
+        {
+            let input = "'ab'".to_string();
+            let mut lexer = Lexer::new(&input);
+
+            assert_eq!(
+                "文字リテラルに複数の文字が含まれています",
+                lexer.take_char_literal().unwrap_err().to_string()
+            );
+        }
+
+        {
+            let input = "'a".to_string();
+            let mut lexer = Lexer::new(&input);
+
+            assert_eq!(
+                "文字リテラルが閉じられていません",
+                lexer.take_char_literal().unwrap_err().to_string()
+            );
+        }
+
+        {
+            let input = "''".to_string();
+            let mut lexer = Lexer::new(&input);
+
+            assert_eq!(
+                "空の文字リテラルです",
+                lexer.take_char_literal().unwrap_err().to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn take_string_literal() {
+        {
+            let input = r#""hello""#.to_string();
+            let mut lexer = Lexer::new(&input);
+
+            assert_eq!(b"hello".to_vec(), lexer.take_string_literal().unwrap());
+        }
+
+        {
+            let input = r#""a\tb\n\"c\"""#.to_string();
+            let mut lexer = Lexer::new(&input);
+
+            assert_eq!(
+                b"a\tb\n\"c\"".to_vec(),
+                lexer.take_string_literal().unwrap()
+            );
+        }
+
+        {
+            let input = r#""abc"#.to_string();
+            let mut lexer = Lexer::new(&input);
+
+            assert_eq!(
+                "文字列リテラルが閉じられていません",
+                lexer.take_string_literal().unwrap_err().to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn check_numeric_range() {
+        assert_eq!(
+            Ok(isize::MAX),
+            Lexer::check_numeric_range("9223372036854775807")
+        );
+        assert_eq!(
+            Ok(isize::MIN),
+            Lexer::check_numeric_range("-9223372036854775808")
+        );
+        assert_eq!(
+            Err("整数オーバーフローです: 9223372036854775808".to_string()),
+            Lexer::check_numeric_range("9223372036854775808")
+        );
+        assert_eq!(
+            Err("整数オーバーフローです: -9223372036854775809".to_string()),
+            Lexer::check_numeric_range("-9223372036854775809")
+        );
+        assert_eq!(
+            Err("数ではありません".to_string()),
+            Lexer::check_numeric_range("")
+        );
+    }
+
+    #[test]
+    fn process_escape() {
+        let cases = [
+            (r"n", '\n'),
+            (r"t", '\t'),
+            (r"r", '\r'),
+            (r"a", '\u{7}'),
+            (r"\", '\\'),
+            (r#"""#, '"'),
+            (r"'", '\''),
+            (r"0", '\0'),
+            (r"x41", 'A'),
+            (r"101", 'A'),
+            (r"xff", '\u{ff}'),
+            (r"0123", '\n'),
+        ];
+
+        for (input, expected) in cases {
+            let mut chars = input.chars();
+
+            assert_eq!(Ok(expected), Lexer::process_escape(&mut chars), "{}", input);
+        }
+
+        // 8進数は3桁、16進数は2桁までしか読まない
+        {
+            let mut chars = "0123".chars();
+
+            Lexer::process_escape(&mut chars).unwrap();
+            assert_eq!("3", chars.as_str());
+        }
+
+        {
+            let mut chars = "x414".chars();
+
+            Lexer::process_escape(&mut chars).unwrap();
+            assert_eq!("4", chars.as_str());
+        }
+
+        assert_eq!(
+            Err("不明なエスケープシーケンス`\\q`です".to_string()),
+            Lexer::process_escape(&mut "q".chars())
+        );
+        assert!(Lexer::process_escape(&mut "xg".chars()).is_err());
+        assert!(Lexer::process_escape(&mut "777".chars()).is_err());
+    }
+
+    #[test]
+    fn escape_error_position() {
+        let input = r#"1 + "ab\q""#.to_string();
+        let mut lexer = Lexer::new(&input);
+
+        let err = lexer.tokenize().err().unwrap();
+
+        assert_eq!(
+            format!("{}\n       ^ 不明なエスケープシーケンス`\\q`です", input),
+            lexer.error_at(&err)
+        );
+    }
+
+    #[test]
+    fn tokenize_long_expression() {
+        let input = format!("{}1", "1 + ".repeat(10000));
+        let mut lexer = Lexer::new(&input);
+        let tokens = lexer.tokenize().ok().unwrap();
+
+        // 数と記号が交互に並び、最後にEOFが付く
+        assert_eq!(20002, tokens.len());
+        assert_eq!(input.len() - 1, tokens[20000].pos);
+        assert_eq!(input.len(), tokens[20001].pos);
+    }
+
+    #[test]
+    fn char_literal_value() {
+        // 0x80以上のバイトは、文字列から1バイト読み込んだときと同じ負の値になる
+        let cases = [
+            (r"'\xff'", -1),
+            (r"'\200'", -128),
+            (r"'\x7f'", 127),
+            ("'A'", 65),
+        ];
+
+        for (input, expected) in cases {
+            let input = input.to_string();
+            let mut lexer = Lexer::new(&input);
+            let tokens = lexer.tokenize().ok().unwrap();
+
+            assert!(
+                matches!(tokens[0].kind, TokenKind::Num(n) if n == expected),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn peek_nth() {
+        let input = "(char)1".to_string();
+        let mut lexer = Lexer::new(&input);
+
+        lexer.tokenize().ok().unwrap();
+
+        // 先読みしてもトークンは読み進めない
+        assert_eq!(Some(Reserved::LeftParen), lexer.peek_nth(0));
+        assert_eq!(Some(Reserved::Char), lexer.peek_nth(1));
+        assert_eq!(Some(Reserved::RightParen), lexer.peek_nth(2));
+        assert_eq!(None, lexer.peek_nth(3));
+        assert_eq!(None, lexer.peek_nth(10));
+        assert!(lexer.consume(Reserved::LeftParen));
+        assert_eq!(Some(Reserved::Char), lexer.peek_nth(0));
+    }
+
+    #[test]
+    fn decrement_longest_match() {
+        // `--`は1つのトークンになり、空白を挟んだ`- -`は2つの`-`になる
+        let cases = [
+            ("--3", vec!["--", "3"]),
+            ("- -3", vec!["-", "-", "3"]),
+            ("---3", vec!["--", "-", "3"]),
+            ("1--2", vec!["1", "--", "2"]),
+            ("1- -2", vec!["1", "-", "-", "2"]),
+        ];
+
+        for (input, expected) in cases {
+            let input = input.to_string();
+            let mut lexer = Lexer::new(&input);
+            let tokens = lexer.tokenize().ok().unwrap();
+            let lexemes: Vec<&str> = tokens[..tokens.len() - 1]
+                .iter()
+                .map(|token| token.lexeme.as_str())
+                .collect();
+
+            assert_eq!(expected, lexemes, "{}", input);
+        }
+    }
+
+    #[test]
+    fn comparison_adjacency() {
+        // 2文字の比較演算子は1文字の演算子より先に照合する
+        let cases = [
+            ("1>=2", vec!["1", ">=", "2"]),
+            ("1<=2", vec!["1", "<=", "2"]),
+            ("1==2", vec!["1", "==", "2"]),
+            ("1!=2", vec!["1", "!=", "2"]),
+            ("1>2", vec!["1", ">", "2"]),
+            ("1<2", vec!["1", "<", "2"]),
+            ("1<=>2", vec!["1", "<=", ">", "2"]),
+            ("1>=<2", vec!["1", ">=", "<", "2"]),
+            ("1<<=2", vec!["1", "<", "<=", "2"]),
+            ("1==>=2", vec!["1", "==", ">=", "2"]),
+        ];
+
+        for (input, expected) in cases {
+            let input = input.to_string();
+            let mut lexer = Lexer::new(&input);
+            let tokens = lexer.tokenize().ok().unwrap();
+            let lexemes: Vec<&str> = tokens[..tokens.len() - 1]
+                .iter()
+                .map(|token| token.lexeme.as_str())
+                .collect();
+
+            assert_eq!(expected, lexemes, "{}", input);
+        }
+
+        // `=`だけの演算子はまだないので、2文字の演算子に続く`=`や離れた`=`はその位置でエラーになる
+        let errors = [
+            ("1 > = 2", 4),
+            ("1>==2", 3),
+            ("1===2", 3),
+            ("1!==2", 3),
+            ("1 ! 2", 2),
+        ];
+
+        for (input, pos) in errors {
+            let input = input.to_string();
+            let mut lexer = Lexer::new(&input);
+
+            assert_eq!(
+                Some(CompileError::lex(pos, "予期しない文字です")),
+                lexer.tokenize().err(),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn error_at() {
+        let input = "1 + あ".to_string();
+        let mut lexer = Lexer::new(&input);
+
+        let err = lexer.tokenize().err().unwrap();
+
+        assert_eq!("1 + あ\n    ^ トークナイズできません", lexer.error_at(&err));
+    }
+
+    #[test]
+    fn digit_separator_error_position() {
+        let input = "1 + 1__0".to_string();
+        let mut lexer = Lexer::new(&input);
+
+        let err = lexer.tokenize().err().unwrap();
+
+        assert_eq!(
+            "1 + 1__0\n     ^ `1` の後ろの `_` は数字ではありません",
+            lexer.error_at(&err)
+        );
+    }
+
+    #[test]
+    fn digit_separator_tokenize() {
+        // 区切りは数字の間に1つだけ置ける
+        let cases = [
+            ("_1", "_1\n^ 数字の区切りの `_` は数字の間にしか置けません"),
+            ("1_", "1_\n ^ `1` の後ろの `_` は数字ではありません"),
+            ("1__2", "1__2\n ^ `1` の後ろの `_` は数字ではありません"),
+        ];
+
+        for (input, expected) in cases {
+            let input = input.to_string();
+            let mut lexer = Lexer::new(&input);
+            let err = lexer.tokenize().err().unwrap();
+
+            assert_eq!(expected, lexer.error_at(&err), "{}", input);
+        }
+    }
+
+    #[test]
+    fn unterminated_string_position() {
+        // 閉じられていない文字列リテラルは開き引用符の位置で報告する
+        let input = r#"1 + "abc"#.to_string();
+        let mut lexer = Lexer::new(&input);
+
+        let err = lexer.tokenize().err().unwrap();
+
+        assert_eq!(
+            "1 + \"abc\n    ^ 文字列リテラルが閉じられていません",
+            lexer.error_at(&err)
+        );
+    }
+
+    #[test]
+    fn overflow_error_position() {
+        let input = "1 + 99999999999999999999".to_string();
+        let mut lexer = Lexer::new(&input);
+
+        let err = lexer.tokenize().err().unwrap();
+
+        assert_eq!(
+            CompileError::lex(4, "整数オーバーフローです: 99999999999999999999"),
+            err
+        );
+        assert_eq!(
+            "1 + 99999999999999999999\n    ^ 整数オーバーフローです: 99999999999999999999",
+            lexer.error_at(&err)
+        );
+
+        // isizeの最大値に1を足した数
+        let input = "9223372036854775808".to_string();
+        let mut lexer = Lexer::new(&input);
+
+        assert_eq!(
+            Some(CompileError::lex(
+                0,
+                "整数オーバーフローです: 9223372036854775808"
+            )),
+            lexer.tokenize().err()
+        );
+
+        // isizeの最大値は収まる
+        let input = "9223372036854775807".to_string();
+        let mut lexer = Lexer::new(&input);
+
+        assert!(lexer.tokenize().is_ok());
+
+        // 形の正しくない数は別のメッセージになる
+        let input = "1__0".to_string();
+        let mut lexer = Lexer::new(&input);
+
+        assert_eq!(
+            "`1` の後ろの `_` は数字ではありません",
+            lexer.tokenize().err().unwrap().message()
+        );
+    }
+
+    #[test]
+    fn invalid_digit_message() {
+        // 読み込めた部分の数値と、数字でない文字の両方を示す
+        let cases = [
+            ("123a", 3, "`123` の後ろの `a` は数字ではありません"),
+            ("1 + 0x1", 5, "`0` の後ろの `x` は数字ではありません"),
+            ("45_b", 2, "`45` の後ろの `_` は数字ではありません"),
+            // 先頭の0の後ろに数字が続く場合は、数値の先頭を指す
+            ("01", 0, "先頭に 0 を置いた数値はサポートされていません"),
+            (
+                "1 + 007",
+                4,
+                "先頭に 0 を置いた数値はサポートされていません",
+            ),
+        ];
+
+        for (input, pos, message) in cases {
+            let input = input.to_string();
+            let mut lexer = Lexer::new(&input);
+
+            assert_eq!(
+                Some(CompileError::lex(pos, message)),
+                lexer.tokenize().err(),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn token_to_json() {
+        let input = r#"1 + "a\"b""#.to_string();
+        let mut lexer = Lexer::new(&input);
+        let tokens = lexer.tokenize().ok().unwrap();
+        let json: Vec<String> = tokens.iter().map(|token| token.to_json()).collect();
+
+        assert_eq!(
+            vec![
+                r#"{"kind":"Num","lexeme":"1","span":[0,1]}"#,
+                r#"{"kind":"Reserved","lexeme":"+","span":[2,3]}"#,
+                r#"{"kind":"Str","lexeme":"\"a\\\"b\"","span":[4,10]}"#,
+                r#"{"kind":"Eof","lexeme":"","span":[10,10]}"#,
+            ],
+            json
+        );
     }
 
     #[test]
@@ -426,11 +1319,31 @@ mod test {
         let input = "hello".to_string();
         let lexer = Lexer::new(&input);
 
-        assert_eq!(true, lexer.start_with("hello"));
-        assert_eq!(true, lexer.start_with("h"));
-        assert_eq!(false, lexer.start_with("adsf"));
-        assert_eq!(false, lexer.start_with("ha"));
-        assert_eq!(false, lexer.start_with("ha"));
-        assert_eq!(false, lexer.start_with("hello world"));
+        assert!(lexer.start_with("hello"));
+        assert!(lexer.start_with("h"));
+        assert!(!lexer.start_with("adsf"));
+        assert!(!lexer.start_with("ha"));
+        assert!(!lexer.start_with("ha"));
+        assert!(!lexer.start_with("hello world"));
+        assert!(lexer.start_with(""));
+
+        // 入力が先に尽きる場合
+        let input = "=".to_string();
+        let lexer = Lexer::new(&input);
+
+        assert!(lexer.start_with("="));
+        assert!(!lexer.start_with("=="));
+
+        let input = String::new();
+        let lexer = Lexer::new(&input);
+
+        assert!(!lexer.start_with("-"));
+
+        // 複数バイトの文字は文字単位で比べる
+        let input = "あいう".to_string();
+        let lexer = Lexer::new(&input);
+
+        assert!(lexer.start_with("あい"));
+        assert!(!lexer.start_with("あいうえ"));
     }
 }
@@ -0,0 +1,94 @@
+pub mod codegen;
+pub mod codegen_aarch64;
+pub mod ctype;
+pub mod error;
+pub mod lexer;
+pub mod optimize;
+pub mod parser;
+
+pub use crate::error::CompileError;
+
+use crate::{
+    codegen::{Codegen, SyntaxMode},
+    codegen_aarch64::Aarch64Codegen,
+    lexer::{Lexer, Token},
+    optimize::{fold_constants, strength_reduce},
+    parser::{Node, Parser},
+};
+
+/// 生成するアセンブリの対象アーキテクチャ
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Target {
+    #[default]
+    X86_64,
+    Aarch64,
+}
+
+impl Target {
+    /// `x86_64-linux-gnu`のようなターゲットの名前から対象アーキテクチャを決める
+    /// `x86_64`や`arm64`のようにアーキテクチャ名だけでもよい
+    pub fn from_triple(triple: &str) -> Option<Target> {
+        match triple {
+            "x86_64-linux-gnu" | "x86_64" => Some(Target::X86_64),
+            "aarch64-linux-gnu" | "aarch64" | "arm64" => Some(Target::Aarch64),
+            _ => None,
+        }
+    }
+}
+
+/// ソースコードを受け取り、字句解析からコード生成までを行う
+pub struct Compiler {
+    source: String,     // コンパイルするソースコード
+    target: Target,     // 対象アーキテクチャ
+    syntax: SyntaxMode, // 出力するアセンブリの記法(x86-64のみ)
+}
+
+impl Compiler {
+    pub fn new(source: &str) -> Compiler {
+        Compiler {
+            source: source.to_string(),
+            target: Target::X86_64,
+            syntax: SyntaxMode::Intel,
+        }
+    }
+
+    /// 対象アーキテクチャを設定する
+    pub fn set_target(&mut self, target: Target) {
+        self.target = target;
+    }
+
+    /// 出力するアセンブリの記法を設定する
+    pub fn set_syntax(&mut self, syntax: SyntaxMode) {
+        self.syntax = syntax;
+    }
+
+    /// ソースコードを字句解析し、トークン列を返す
+    pub fn tokenize(&self) -> Result<Vec<Token>, CompileError> {
+        Lexer::new(&self.source).tokenize()
+    }
+
+    /// ソースコードを構文解析し、抽象構文木を返す
+    pub fn parse(&self) -> Result<Node, CompileError> {
+        let mut lexer = Lexer::new(&self.source);
+
+        lexer.tokenize()?;
+
+        Parser::new(lexer).program()
+    }
+
+    /// ソースコードをコンパイルし、アセンブリを返す
+    /// エラーの位置を入力と合わせて表示するには`Lexer::error_at`を使う
+    pub fn compile(&mut self) -> Result<String, CompileError> {
+        let node = strength_reduce(fold_constants(self.parse()?)?);
+
+        match self.target {
+            Target::X86_64 => Codegen::new(self.syntax).gen_program(node),
+            Target::Aarch64 => Aarch64Codegen::new().gen_program(node),
+        }
+    }
+}
+
+/// ソースコードを既定の設定(x86-64、Intel記法)でコンパイルし、アセンブリを返す
+pub fn compile(source: &str) -> Result<String, CompileError> {
+    Compiler::new(source).compile()
+}
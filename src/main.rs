@@ -1,52 +1,208 @@
-mod codegen;
-mod lexer;
-mod parser;
+use std::{
+    env, fmt,
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+    process::{self, Command},
+};
 
-use lexer::Lexer;
-use std::{env, fmt, process};
+use c_compiler_edu::{
+    codegen::SyntaxMode,
+    lexer::{Lexer, Token},
+    Compiler, Target,
+};
 
-use crate::{codegen::gen, parser::Parser};
+const USAGE: &str = "\
+使い方: 9cc [--emit=tokens|ast|asm] [--att-syntax] [--target=<ターゲット>] [-o <出力ファイル>] <プログラム>
+       9cc --repl";
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+/// `--emit`で選ぶ出力の段階
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Emit {
+    Tokens, // 字句解析したトークン列(JSON)
+    Ast,    // 構文解析した抽象構文木(S式)
+    Asm,    // 生成したアセンブリ
+}
 
-    if args.len() != 2 {
-        eprintln!("引数の個数が正しくありません");
-        process::exit(1);
+fn main() {
+    if env::args().skip(1).eq(["--repl"]) {
+        repl();
+        return;
     }
 
-    // 字句解析器を初期化
-    let mut lexer = Lexer::new(&args[1]);
+    let mut input = None;
+    let mut emit = Emit::Asm;
+    let mut syntax = SyntaxMode::Intel;
+    let mut target = Target::X86_64;
+    let mut output = None;
+    let mut args = env::args().skip(1);
 
-    // トークナイズしつつエラーがあればプログラムを止める
-    if let Err(msg) = lexer.tokenize() {
-        error(&mut lexer, msg);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--att-syntax" => syntax = SyntaxMode::Att,
+            "--target" => match args.next().as_deref().map(Target::from_triple) {
+                Some(Some(parsed)) => target = parsed,
+                Some(None) => error("対応していないターゲットです"),
+                None => error("--targetの後にターゲットがありません"),
+            },
+            _ if arg.starts_with("--target=") => match Target::from_triple(&arg[9..]) {
+                Some(parsed) => target = parsed,
+                None => error(format!("{}は対応していないターゲットです", &arg[9..])),
+            },
+            "-o" | "--output" => match args.next() {
+                Some(path) => output = Some(path),
+                None => error(format!("{}の後に出力ファイル名がありません", arg)),
+            },
+            // `--emit-tokens`と`--emit-ast`は以前からある別名
+            "--emit=tokens" | "--emit-tokens" => emit = Emit::Tokens,
+            "--emit=ast" | "--emit-ast" => emit = Emit::Ast,
+            "--emit=asm" => emit = Emit::Asm,
+            _ if arg.starts_with("--emit=") => {
+                error(format!("{}は不明な出力の段階です\n{}", &arg[7..], USAGE))
+            }
+            // `--3`のようなプログラムと区別するため、英字が続くものだけをオプションとみなす
+            _ if arg.starts_with("--") && arg[2..].starts_with(char::is_alphabetic) => {
+                error(format!("{}は不明なオプションです\n{}", arg, USAGE))
+            }
+            _ if input.is_none() => input = Some(arg),
+            _ => error("引数の個数が正しくありません"),
+        }
     }
 
-    // パーサーを初期化
-    let mut parser = Parser::new(lexer);
-    let node = match parser.expr() {
-        Ok(node) => node,
-        Err(msg) => {
-            error(&mut parser.get_lexer(), msg);
+    let input = match input {
+        Some(input) => input,
+        None => {
+            error("引数の個数が正しくありません");
             return;
         }
     };
+    let mut compiler = Compiler::new(&input);
+
+    compiler.set_target(target);
+    compiler.set_syntax(syntax);
+
+    // `--emit`で選んだ段階の結果を出力する
+    let result = match emit {
+        Emit::Tokens => compiler
+            .tokenize()
+            .map(|tokens| format!("{}\n", tokens_json(&tokens))),
+        Emit::Ast => compiler.parse().map(|node| format!("{}\n", node)),
+        Emit::Asm => compiler.compile(),
+    };
+
+    let text = match result {
+        Ok(text) => text,
+        // エラーは入力プログラムとエラー箇所を添えて報告する
+        Err(err) => {
+            error(Lexer::new(&input).error_at(&err));
+            return;
+        }
+    };
+
+    // 出力先の指定がなければ標準出力に書く
+    let mut out: Box<dyn Write> = match output {
+        Some(path) => match File::create(&path) {
+            Ok(file) => Box::new(file),
+            Err(err) => {
+                error(format!("出力ファイル{}を開けません: {}", path, err));
+                return;
+            }
+        },
+        None => Box::new(io::stdout()),
+    };
+
+    if let Err(err) = out.write_all(text.as_bytes()) {
+        error(format!("出力を書き込めません: {}", err));
+    }
+}
+
+/// 標準入力から1行ずつ式を読み込み、実行した終了コードを式の値として表示する
+/// 入力が終わったら終了する
+fn repl() {
+    let dir = env::temp_dir().join(format!("9cc-repl-{}", process::id()));
+
+    if let Err(err) = fs::create_dir_all(&dir) {
+        error(format!("一時ディレクトリを作れません: {}", err));
+    }
 
-    // アセンブリの前半部分を出力
-    println!(".intel_syntax noprefix");
-    println!(".globl main");
-    println!("main:");
+    loop {
+        print!("> ");
+        io::stdout().flush().ok();
+
+        let mut line = String::new();
+
+        match io::stdin().read_line(&mut line) {
+            Ok(0) => {
+                println!();
+                break;
+            }
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("入力を読み込めません: {}", err);
+                break;
+            }
+        }
+
+        let source = line.trim_end();
+
+        if source.is_empty() {
+            continue;
+        }
+
+        // エラーがあっても報告するだけで、次の入力を待つ
+        match run(source, &dir) {
+            Ok(code) => println!("{}", code),
+            Err(msg) => eprintln!("{}", msg),
+        }
+    }
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+/// ソースコードをコンパイルし、ccでアセンブル・リンクして実行したときの終了コードを返す
+/// 生成したファイルは`dir`に置く
+fn run(source: &str, dir: &Path) -> Result<i32, String> {
+    let source = source.to_string();
+    let asm = Compiler::new(&source)
+        .compile()
+        .map_err(|err| Lexer::new(&source).error_at(&err))?;
+    let asm_path = dir.join("tmp.s");
+    let exe_path = dir.join("tmp");
+
+    fs::write(&asm_path, asm).map_err(|err| format!("一時ファイルに書き込めません: {}", err))?;
+
+    // リンカの警告で表示が乱れないよう、ccの出力は失敗したときだけ見せる
+    let output = Command::new("cc")
+        .arg("-o")
+        .arg(&exe_path)
+        .arg(&asm_path)
+        .output()
+        .map_err(|err| format!("ccを実行できません: {}", err))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "アセンブルに失敗しました\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    let status = Command::new(&exe_path)
+        .status()
+        .map_err(|err| format!("プログラムを実行できません: {}", err))?;
+
+    status
+        .code()
+        .ok_or_else(|| "プログラムがシグナルで終了しました".to_string())
+}
 
-    gen(node);
+/// トークン列をJSONの配列にする
+fn tokens_json(tokens: &[Token]) -> String {
+    let json: Vec<String> = tokens.iter().map(|token| token.to_json()).collect();
 
-    // スタックトップに式全体の値が残っているはずなので、RAXにロードして関数からの返り値とする
-    println!("  pop rax");
-    println!("  ret");
+    format!("[{}]", json.join(","))
 }
 
-fn error<'a>(lexer: &mut Lexer<'a>, msg: impl fmt::Display) {
-    let msg = lexer.error_at(msg);
+fn error(msg: impl fmt::Display) {
     eprintln!("{}", msg);
     process::exit(1);
 }
@@ -0,0 +1,211 @@
+use crate::{
+    error::CompileError,
+    parser::{Node, NodeKind},
+};
+
+/// 両辺が数値の四則演算を、計算結果の数値に置き換える
+/// 子から順に畳み込むので、`2 * 3 + 4 * 5`は`26`になる
+/// 実行時と同じく64ビットで計算し、あふれた場合は折り返す
+pub fn fold_constants(node: Node) -> Result<Node, CompileError> {
+    let lhs = match node.get_lhs() {
+        Some(lhs) => Some(Box::new(fold_constants(*lhs)?)),
+        None => None,
+    };
+    let rhs = match node.get_rhs() {
+        Some(rhs) => Some(Box::new(fold_constants(*rhs)?)),
+        None => None,
+    };
+
+    let (l, r) = match (lhs.as_deref(), rhs.as_deref()) {
+        (Some(l), Some(r)) => match (l.get_kind(), r.get_kind()) {
+            (NodeKind::Num(l), NodeKind::Num(r)) => (l, r),
+            _ => return Ok(rebuild(&node, node.get_kind(), lhs, rhs)),
+        },
+        _ => return Ok(rebuild(&node, node.get_kind(), lhs, rhs)),
+    };
+
+    let num = match node.get_kind() {
+        NodeKind::Add => l.wrapping_add(r),
+        NodeKind::Sub => l.wrapping_sub(r),
+        NodeKind::Mul => l.wrapping_mul(r),
+        NodeKind::Div => {
+            // 割り算の演算子の位置で報告する
+            if r == 0 {
+                let message = "0で割っています".to_string();
+
+                return Err(match node.get_pos() {
+                    Some(pos) => CompileError::parse(pos, message),
+                    None => CompileError::CodegenError { message },
+                });
+            }
+
+            l.wrapping_div(r)
+        }
+        kind => return Ok(rebuild(&node, kind, lhs, rhs)),
+    };
+
+    Ok(rebuild(&node, NodeKind::Num(num), None, None))
+}
+
+/// 2の累乗による乗算を左シフトに置き換える
+/// 1による乗算は掛けられる数そのものに置き換える
+/// 符号付き整数の除算は負の数で結果が変わるので、右シフトには置き換えない
+pub fn strength_reduce(node: Node) -> Node {
+    let lhs = node.get_lhs().map(|lhs| Box::new(strength_reduce(*lhs)));
+    let rhs = node.get_rhs().map(|rhs| Box::new(strength_reduce(*rhs)));
+
+    if let (NodeKind::Mul, Some(l), Some(r)) = (node.get_kind(), &lhs, &rhs) {
+        // 定数はどちらの辺にあってもよい
+        let operands = match (l.get_kind(), r.get_kind()) {
+            (_, NodeKind::Num(k)) => Some((l, k)),
+            (NodeKind::Num(k), _) => Some((r, k)),
+            _ => None,
+        };
+
+        if let Some((other, k)) = operands {
+            if k == 1 {
+                return *other.clone();
+            }
+
+            if k > 0 && (k as usize).is_power_of_two() {
+                let shift = Node::new(NodeKind::Num(k.trailing_zeros() as isize), None, None);
+
+                return rebuild(
+                    &node,
+                    NodeKind::Shl,
+                    Some(other.clone()),
+                    Some(Box::new(shift)),
+                );
+            }
+        }
+    }
+
+    rebuild(&node, node.get_kind(), lhs, rhs)
+}
+
+/// 最適化で置き換えたノードを作る
+/// 後からエラーを報告できるよう、元のノードの入力プログラム中の位置を引き継ぐ
+fn rebuild(node: &Node, kind: NodeKind, lhs: Option<Box<Node>>, rhs: Option<Box<Node>>) -> Node {
+    let new = Node::new(kind, lhs, rhs);
+
+    match node.get_pos() {
+        Some(pos) => new.with_pos(pos),
+        None => new,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fold_constants, strength_reduce};
+    use crate::{
+        codegen::{Codegen, SyntaxMode},
+        error::CompileError,
+        lexer::Lexer,
+        parser::{Node, Parser},
+    };
+
+    fn fold(input: &str) -> Result<Node, CompileError> {
+        let input = input.to_string();
+        let mut lexer = Lexer::new(&input);
+
+        lexer.tokenize().unwrap();
+
+        fold_constants(Parser::new(lexer).program().ok().unwrap())
+    }
+
+    #[test]
+    fn fold_arithmetic() {
+        let cases = [
+            ("2 * 3 + 4 * 5", "(num 26)"),
+            ("(1 + 2) * 3", "(num 9)"),
+            ("7 / 2", "(num 3)"),
+            ("-7 / 2", "(num -3)"),
+            ("10 - 4 - 3", "(num 3)"),
+            ("9223372036854775807 + 1", "(num -9223372036854775808)"),
+            // 比較は畳み込まず、被演算子だけを畳み込む
+            ("1 + 1 == 2", "(eq (num 2) (num 2))"),
+            // ポインタの加算では、添字に要素のサイズを掛けた部分だけを畳み込む
+            (r#""ab" + 1"#, r#"(add (str "ab") (num 1))"#),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(expected, fold(input).ok().unwrap().to_string(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn fold_division_by_zero() {
+        let err = fold("1 / (2 - 2)").err().unwrap();
+
+        assert_eq!("0で割っています", err.to_string());
+        assert_eq!(Some(2), err.span().map(|span| span.start));
+    }
+
+    #[test]
+    fn keep_position() {
+        // 畳み込みや置き換えをしても、二項演算子の位置は残る
+        let node = strength_reduce(fold(r#"*"a" * 4 == 2 * 3"#).ok().unwrap());
+        let lhs = node.get_lhs().unwrap();
+
+        assert_eq!(
+            "(eq (shl (deref (str \"a\")) (num 2)) (num 6))",
+            node.to_string()
+        );
+        assert_eq!(Some(9), node.get_pos());
+        assert_eq!(Some(5), lhs.get_pos());
+        assert_eq!(Some(14), node.get_rhs().unwrap().get_pos());
+    }
+
+    #[test]
+    fn gen_folded() {
+        let node = fold("2 * 3 + 4 * 5").ok().unwrap();
+        let asm = Codegen::new(SyntaxMode::Intel).gen_program(node).unwrap();
+
+        // 計算の命令は残らず、26をRAXに入れるだけになる
+        let pushes: Vec<&str> = asm
+            .lines()
+            .filter(|line| line.trim_start().starts_with("push"))
+            .collect();
+
+        assert_eq!(vec!["  push rbp"], pushes);
+        assert!(asm.contains("  mov rax, 26\n"));
+        assert!(!asm.contains("imul"));
+    }
+
+    #[test]
+    fn reduce_power_of_two() {
+        let cases = [
+            ("*\"a\" * 1", r#"(deref (str "a"))"#),
+            ("*\"a\" * 2", r#"(shl (deref (str "a")) (num 1))"#),
+            ("*\"a\" * 4", r#"(shl (deref (str "a")) (num 2))"#),
+            ("8 * *\"a\"", r#"(shl (deref (str "a")) (num 3))"#),
+            ("*\"a\" * 16", r#"(shl (deref (str "a")) (num 4))"#),
+            // 2の累乗でなければそのまま
+            ("*\"a\" * 3", r#"(mul (deref (str "a")) (num 3))"#),
+            ("*\"a\" * -2", r#"(mul (deref (str "a")) (num -2))"#),
+            ("*\"a\" / 2", r#"(div (deref (str "a")) (num 2))"#),
+        ];
+
+        for (input, expected) in cases {
+            let node = strength_reduce(fold(input).ok().unwrap());
+
+            assert_eq!(expected, node.to_string(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn gen_shift() {
+        let gen = |input: &str| {
+            let node = strength_reduce(fold(input).ok().unwrap());
+
+            Codegen::new(SyntaxMode::Intel).gen_program(node).unwrap()
+        };
+
+        assert!(gen("*\"a\" * 4").contains("  sal rax, 2\n"));
+
+        let asm = gen("*\"a\" * 1");
+
+        assert!(!asm.contains("sal"));
+        assert!(!asm.contains("imul"));
+    }
+}
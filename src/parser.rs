@@ -1,7 +1,13 @@
-use crate::lexer::{Lexer, Reserved};
+use std::fmt;
+
+use crate::{
+    ctype::CType,
+    error::CompileError,
+    lexer::{Lexer, Reserved},
+};
 
 /// 抽象構文木のノードの種類
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub enum NodeKind {
     Add,
     Sub,
@@ -11,7 +17,35 @@ pub enum NodeKind {
     Lt,
     Le,
     Ne,
+    Shl,         // 左シフト(最適化で乗算から作られる)
+    Addr,        // アドレス演算子`&`
+    Deref,       // 間接参照演算子`*`
+    Cast(CType), // 型変換`(型)式`
     Num(isize),
+    Str(Vec<u8>),
+}
+
+impl fmt::Display for NodeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            NodeKind::Add => "add",
+            NodeKind::Sub => "sub",
+            NodeKind::Mul => "mul",
+            NodeKind::Div => "div",
+            NodeKind::Eq => "eq",
+            NodeKind::Lt => "lt",
+            NodeKind::Le => "le",
+            NodeKind::Ne => "ne",
+            NodeKind::Shl => "shl",
+            NodeKind::Addr => "addr",
+            NodeKind::Deref => "deref",
+            NodeKind::Cast(_) => "cast",
+            NodeKind::Num(_) => "num",
+            NodeKind::Str(_) => "str",
+        };
+
+        write!(f, "{}", s)
+    }
 }
 
 /// 抽象構文木のノード
@@ -20,15 +54,31 @@ pub struct Node {
     kind: NodeKind,         // ノードの型
     lhs: Option<Box<Node>>, // 左辺
     rhs: Option<Box<Node>>, // 右辺
+    pos: Option<usize>,     // 二項演算子の入力プログラム中の位置
 }
 
 impl Node {
     pub fn new(kind: NodeKind, lhs: Option<Box<Node>>, rhs: Option<Box<Node>>) -> Node {
-        Node { kind, lhs, rhs }
+        Node {
+            kind,
+            lhs,
+            rhs,
+            pos: None,
+        }
+    }
+
+    /// 最適化などで見つかったエラーを報告できるよう、入力プログラム中の位置を持たせる
+    pub fn with_pos(mut self, pos: usize) -> Node {
+        self.pos = Some(pos);
+        self
+    }
+
+    pub fn get_pos(&self) -> Option<usize> {
+        self.pos
     }
 
     pub fn get_kind(&self) -> NodeKind {
-        self.kind
+        self.kind.clone()
     }
 
     pub fn get_lhs(&self) -> Option<Box<Node>> {
@@ -38,6 +88,186 @@ impl Node {
     pub fn get_rhs(&self) -> Option<Box<Node>> {
         self.rhs.clone()
     }
+
+    /// ノードが表す式の型
+    pub fn get_type(&self) -> CType {
+        match self.kind {
+            // 文字列リテラルは終端の0を含めた長さのcharの配列
+            NodeKind::Str(ref bytes) => CType::Array(Box::new(CType::Char), bytes.len() + 1),
+            NodeKind::Addr => CType::Ptr(Box::new(self.lhs_type())),
+            NodeKind::Cast(ref ty) => ty.clone(),
+            // 構文解析の時点で被演算子がポインタであることを確かめている
+            NodeKind::Deref => self.lhs_type().base().unwrap_or(CType::Int),
+            // ポインタと整数の加減算では、構文解析の時点でポインタを左辺に置いている
+            NodeKind::Add => match self.lhs_type().decay() {
+                CType::Ptr(base) => CType::Ptr(base),
+                lhs => CType::usual_arith_conv(&lhs, &self.rhs_type()),
+            },
+            NodeKind::Sub => match (self.lhs_type().decay(), self.rhs_type()) {
+                (CType::Ptr(base), rhs) if rhs.base().is_none() => CType::Ptr(base),
+                // ポインタ同士の差は要素数なのでint
+                (lhs, rhs) if lhs.base().is_some() || rhs.base().is_some() => CType::Int,
+                (lhs, rhs) => CType::usual_arith_conv(&lhs, &rhs),
+            },
+            NodeKind::Mul | NodeKind::Div => {
+                CType::usual_arith_conv(&self.lhs_type(), &self.rhs_type())
+            }
+            // シフトの結果は左辺の型を格上げした型
+            NodeKind::Shl => CType::usual_arith_conv(&self.lhs_type(), &CType::Int),
+            _ => CType::Int,
+        }
+    }
+
+    fn lhs_type(&self) -> CType {
+        match &self.lhs {
+            Some(lhs) => lhs.get_type(),
+            None => CType::Int,
+        }
+    }
+
+    fn rhs_type(&self) -> CType {
+        match &self.rhs {
+            Some(rhs) => rhs.get_type(),
+            None => CType::Int,
+        }
+    }
+
+    /// 二項演算の被演算子を符号なしとして扱うかどうか
+    /// ポインタはアドレスなので符号なしで比べる
+    pub fn has_unsigned_operands(&self) -> bool {
+        let (lhs, rhs) = (self.lhs_type().decay(), self.rhs_type().decay());
+
+        if lhs.base().is_some() || rhs.base().is_some() {
+            return true;
+        }
+
+        CType::usual_arith_conv(&lhs, &rhs).is_unsigned()
+    }
+
+    /// メモリ上の位置を持ち、アドレスを取れるノードかどうか
+    pub fn is_lvalue(&self) -> bool {
+        matches!(self.kind, NodeKind::Deref)
+    }
+}
+
+/// `(add (num 1) (num 2))`のようなS式で木の形をそのまま表す
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}", self.kind)?;
+
+        match &self.kind {
+            NodeKind::Num(num) => write!(f, " {}", num)?,
+            NodeKind::Str(bytes) => write!(f, " {:?}", String::from_utf8_lossy(bytes))?,
+            NodeKind::Cast(ty) => write!(f, " {}", ty)?,
+            _ => {}
+        }
+
+        for child in [&self.lhs, &self.rhs].into_iter().flatten() {
+            write!(f, " {}", child)?;
+        }
+
+        write!(f, ")")
+    }
+}
+
+/// 二項演算子の表
+/// (記号, 左側の結合力, 右側の結合力, ノードの種類, 左右の被演算子を入れ替えるか)
+/// 右側の結合力を左側より大きくすることで左結合になる
+/// `>`と`>=`は左右を入れ替えて`<`と`<=`で表す
+/// 入れ替えは左右の被演算子が決まってから行うので左結合は崩れず、
+/// `3 > 2 > 1`は`(3 > 2) > 1`、つまり`1 < (2 < 3)`になる
+const INFIX_OPERATORS: [(Reserved, u8, u8, NodeKind, bool); 10] = [
+    (Reserved::Eq, 1, 2, NodeKind::Eq, false),
+    (Reserved::Ne, 1, 2, NodeKind::Ne, false),
+    (Reserved::Lt, 3, 4, NodeKind::Lt, false),
+    (Reserved::Le, 3, 4, NodeKind::Le, false),
+    (Reserved::Gt, 3, 4, NodeKind::Lt, true),
+    (Reserved::Ge, 3, 4, NodeKind::Le, true),
+    (Reserved::Plus, 5, 6, NodeKind::Add, false),
+    (Reserved::Minus, 5, 6, NodeKind::Sub, false),
+    (Reserved::Asterisk, 7, 8, NodeKind::Mul, false),
+    (Reserved::Slash, 7, 8, NodeKind::Div, false),
+];
+
+/// 単項演算子の結合力
+/// どの二項演算子よりも強く結合する
+const PREFIX_BINDING_POWER: u8 = 9;
+
+fn new_binary(kind: NodeKind, lhs: Node, rhs: Node) -> Node {
+    Node::new(kind, Some(Box::new(lhs)), Some(Box::new(rhs)))
+}
+
+fn new_num(num: isize) -> Node {
+    Node::new(NodeKind::Num(num), None, None)
+}
+
+/// 加算のノードを作る
+/// ポインタに整数を足す場合は、整数をポインタの指す先の型のサイズ倍してポインタを左辺に置く
+fn new_add(lhs: Node, rhs: Node) -> Result<Node, String> {
+    match (lhs.get_type().base(), rhs.get_type().base()) {
+        (None, None) => Ok(new_binary(NodeKind::Add, lhs, rhs)),
+        (Some(base), None) => {
+            let rhs = new_binary(NodeKind::Mul, rhs, new_num(base.size()));
+
+            Ok(new_binary(NodeKind::Add, lhs, rhs))
+        }
+        (None, Some(base)) => {
+            let lhs = new_binary(NodeKind::Mul, lhs, new_num(base.size()));
+
+            Ok(new_binary(NodeKind::Add, rhs, lhs))
+        }
+        (Some(_), Some(_)) => Err("ポインタ同士は足せません".to_string()),
+    }
+}
+
+/// 減算のノードを作る
+/// ポインタから整数を引く場合は、整数をポインタの指す先の型のサイズ倍する
+/// ポインタ同士の差は、バイト数の差を要素のサイズで割って要素数にする
+fn new_sub(lhs: Node, rhs: Node) -> Result<Node, String> {
+    match (lhs.get_type().base(), rhs.get_type().base()) {
+        (None, None) => Ok(new_binary(NodeKind::Sub, lhs, rhs)),
+        (Some(base), None) => {
+            let rhs = new_binary(NodeKind::Mul, rhs, new_num(base.size()));
+
+            Ok(new_binary(NodeKind::Sub, lhs, rhs))
+        }
+        (Some(base), Some(_)) => {
+            let diff = new_binary(NodeKind::Sub, lhs, rhs);
+
+            Ok(new_binary(NodeKind::Div, diff, new_num(base.size())))
+        }
+        (None, Some(_)) => Err("整数からポインタは引けません".to_string()),
+    }
+}
+
+/// 乗除算のノードを作る
+/// ポインタは掛けたり割ったりできない
+fn new_mul_div(kind: NodeKind, lhs: Node, rhs: Node) -> Result<Node, String> {
+    if lhs.get_type().base().is_some() || rhs.get_type().base().is_some() {
+        return Err(match kind {
+            NodeKind::Div => "ポインタは割り算できません".to_string(),
+            _ => "ポインタは掛け算できません".to_string(),
+        });
+    }
+
+    Ok(new_binary(kind, lhs, rhs))
+}
+
+/// 比較のノードを作る
+/// ポインタと比べられるのはポインタだけで、`==`と`!=`に限り0とも比べられる
+fn new_compare(kind: NodeKind, lhs: Node, rhs: Node) -> Result<Node, String> {
+    let is_null = |node: &Node| matches!(node.get_kind(), NodeKind::Num(0));
+    let is_equality = matches!(kind, NodeKind::Eq | NodeKind::Ne);
+
+    match (lhs.get_type().base(), rhs.get_type().base()) {
+        (Some(_), None) if !(is_equality && is_null(&rhs)) => {
+            Err("ポインタと整数は比較できません".to_string())
+        }
+        (None, Some(_)) if !(is_equality && is_null(&lhs)) => {
+            Err("ポインタと整数は比較できません".to_string())
+        }
+        _ => Ok(new_binary(kind, lhs, rhs)),
+    }
 }
 
 pub struct Parser<'a> {
@@ -53,178 +283,395 @@ impl<'a> Parser<'a> {
         self.lexer.clone()
     }
 
-    pub fn expr(&mut self) -> Result<Node, String> {
-        self.equality()
+    /// 入力全体を1つの式として解析する
+    /// program = expr
+    pub fn program(&mut self) -> Result<Node, CompileError> {
+        // 空白しかない入力では、最初のトークンを指しても何が悪いのか分かりにくい
+        if self.lexer.at_eof() {
+            return Err(CompileError::parse(0, "空のプログラムです"));
+        }
+
+        let node = self.expr()?;
+
+        self.expect_eof()?;
+
+        Ok(node)
+    }
+
+    /// 入力をすべて読み終えていることを確かめる
+    /// 式の後にトークンが残っていれば、そのトークンを示してエラーにする
+    pub fn expect_eof(&mut self) -> Result<(), CompileError> {
+        if self.lexer.at_eof() {
+            return Ok(());
+        }
+
+        let pos = self.lexer.peek_pos();
+        let message = format!("余分なトークン `{}` があります", self.lexer.peek_lexeme());
+
+        Err(CompileError::parse(pos, message))
     }
 
-    pub fn equality(&mut self) -> Result<Node, String> {
-        let mut node = match self.relational() {
-            Ok(node) => node,
-            Err(msg) => {
-                return Err(msg);
-            }
-        };
+    pub fn expr(&mut self) -> Result<Node, CompileError> {
+        self.parse_expr(0)
+    }
+
+    /// 結合力が`min_bp`以上の二項演算子だけを取り込みながら式を解析する
+    pub fn parse_expr(&mut self, min_bp: u8) -> Result<Node, CompileError> {
+        let mut node = self.unary()?;
 
         loop {
-            let kind = if self.lexer.consume(Reserved::Eq) {
-                NodeKind::Eq
-            } else if self.lexer.consume(Reserved::Ne) {
-                NodeKind::Ne
-            } else {
+            let operator = self.lexer.peek_reserved().and_then(|reserved| {
+                INFIX_OPERATORS
+                    .iter()
+                    .find(|(operator, ..)| *operator == reserved)
+            });
+            let (reserved, left_bp, right_bp, kind, is_reverse) = match operator {
+                Some(operator) => operator,
+                None => {
+                    return Ok(node);
+                }
+            };
+
+            // 結合力の弱い演算子は呼び出し元で処理する
+            if *left_bp < min_bp {
                 return Ok(node);
+            }
+
+            let pos = self.lexer.peek_pos();
+
+            self.lexer.consume(reserved.clone());
+
+            let rhs = self.parse_expr(*right_bp)?;
+            let (lhs, rhs) = if *is_reverse {
+                (rhs, node)
+            } else {
+                (node, rhs)
             };
 
-            match self.relational() {
-                Ok(relational) => {
-                    node = Node::new(kind, Some(Box::new(node)), Some(Box::new(relational)));
-                }
-                Err(msg) => {
-                    return Err(msg);
-                }
+            node = match kind {
+                NodeKind::Add => new_add(lhs, rhs),
+                NodeKind::Sub => new_sub(lhs, rhs),
+                NodeKind::Mul | NodeKind::Div => new_mul_div(kind.clone(), lhs, rhs),
+                _ => new_compare(kind.clone(), lhs, rhs),
             }
+            .map_err(|msg| CompileError::parse(pos, msg))?
+            .with_pos(pos);
         }
     }
 
-    pub fn relational(&mut self) -> Result<Node, String> {
-        let mut node = match self.add() {
-            Ok(node) => node,
-            Err(msg) => {
-                return Err(msg);
+    pub fn unary(&mut self) -> Result<Node, CompileError> {
+        if self.lexer.consume(Reserved::SizeOf) {
+            return self.sizeof();
+        }
+
+        if self.lexer.consume(Reserved::Plus) {
+            return self.parse_expr(PREFIX_BINDING_POWER);
+        }
+
+        // エラーを演算子の位置で報告できるように、読み進める前の位置を残しておく
+        let pos = self.lexer.peek_pos();
+
+        if self.lexer.consume(Reserved::Minus) {
+            let node = self.parse_expr(PREFIX_BINDING_POWER)?;
+
+            return new_sub(new_num(0), node).map_err(|msg| CompileError::parse(pos, msg));
+        }
+
+        // 代入はまだないので、左辺値であってもデクリメントはできない
+        if self.lexer.consume(Reserved::Decrement) {
+            let node = self.parse_expr(PREFIX_BINDING_POWER)?;
+
+            if !node.is_lvalue() {
+                return Err(CompileError::parse(
+                    pos,
+                    "左辺値ではないのでデクリメントできません",
+                ));
             }
-        };
 
-        loop {
-            let (kind, is_reverse) = if self.lexer.consume(Reserved::Lt) {
-                (NodeKind::Lt, false)
-            } else if self.lexer.consume(Reserved::Le) {
-                (NodeKind::Le, false)
-            } else if self.lexer.consume(Reserved::Gt) {
-                (NodeKind::Lt, true)
-            } else if self.lexer.consume(Reserved::Ge) {
-                (NodeKind::Le, true)
-            } else {
-                return Ok(node);
-            };
+            return Err(CompileError::parse(
+                pos,
+                "デクリメントはまだサポートされていません",
+            ));
+        }
 
-            match self.add() {
-                Ok(add) => {
-                    if is_reverse {
-                        node = Node::new(kind, Some(Box::new(add)), Some(Box::new(node)));
-                    } else {
-                        node = Node::new(kind, Some(Box::new(node)), Some(Box::new(add)));
-                    }
-                }
-                Err(msg) => {
-                    return Err(msg);
-                }
+        if self.lexer.consume(Reserved::Ampersand) {
+            let node = self.parse_expr(PREFIX_BINDING_POWER)?;
+
+            if !node.is_lvalue() {
+                return Err(CompileError::parse(
+                    pos,
+                    "左辺値ではないのでアドレスを取れません",
+                ));
             }
+
+            return Ok(Node::new(NodeKind::Addr, Some(Box::new(node)), None));
         }
-    }
 
-    pub fn add(&mut self) -> Result<Node, String> {
-        let mut node = match self.mul() {
-            Ok(node) => node,
-            Err(msg) => {
-                return Err(msg);
+        if self.lexer.consume(Reserved::Asterisk) {
+            let node = self.parse_expr(PREFIX_BINDING_POWER)?;
+
+            if node.get_type().base().is_none() {
+                return Err(CompileError::parse(
+                    pos,
+                    "ポインタではない値を参照しています",
+                ));
             }
-        };
+
+            return Ok(Node::new(NodeKind::Deref, Some(Box::new(node)), None));
+        }
+
+        // `(`の次が型名のときだけキャストとして扱い、それ以外は括弧で囲まれた式とする
+        if self.lexer.peek_reserved() == Some(Reserved::LeftParen) && self.is_type_name(1) {
+            self.lexer.consume(Reserved::LeftParen);
+
+            return self.cast(pos);
+        }
+
+        self.postfix()
+    }
+
+    /// 開き括弧の後ろの`型) 式`を解析する
+    /// `pos`は開き括弧の位置で、エラーはその位置で報告する
+    /// cast = "(" type_name ")" unary
+    pub fn cast(&mut self, pos: usize) -> Result<Node, CompileError> {
+        let ty = self.type_name()?;
+
+        self.lexer.expect(Reserved::RightParen)?;
+
+        let node = self.parse_expr(PREFIX_BINDING_POWER)?;
+
+        if let CType::Array(..) = ty {
+            return Err(CompileError::parse(pos, "配列型にはキャストできません"));
+        }
+
+        Ok(Node::new(NodeKind::Cast(ty), Some(Box::new(node)), None))
+    }
+
+    /// 添字演算子を解析する
+    /// `a[i]`は`*(a + i)`に置き換えるので、`i[a]`と書いても同じ意味になる
+    /// postfix = primary ("[" expr "]")*
+    pub fn postfix(&mut self) -> Result<Node, CompileError> {
+        let mut node = self.primary()?;
 
         loop {
-            if self.lexer.consume(Reserved::Plus) {
-                match self.mul() {
-                    Ok(mul) => {
-                        node = Node::new(NodeKind::Add, Some(Box::new(node)), Some(Box::new(mul)))
-                    }
-                    Err(msg) => {
-                        return Err(msg);
-                    }
-                }
-            } else if self.lexer.consume(Reserved::Minus) {
-                match self.mul() {
-                    Ok(mul) => {
-                        node = Node::new(NodeKind::Sub, Some(Box::new(node)), Some(Box::new(mul)))
-                    }
-                    Err(msg) => {
-                        return Err(msg);
-                    }
-                }
-            } else {
+            let pos = self.lexer.peek_pos();
+
+            if !self.lexer.consume(Reserved::LeftBracket) {
                 return Ok(node);
             }
+
+            let index = self.expr()?;
+
+            self.lexer.expect(Reserved::RightBracket)?;
+
+            let addr = new_add(node, index).map_err(|msg| CompileError::parse(pos, msg))?;
+
+            if addr.get_type().base().is_none() {
+                return Err(CompileError::parse(
+                    pos,
+                    "ポインタではない値を参照しています",
+                ));
+            }
+
+            node = Node::new(NodeKind::Deref, Some(Box::new(addr)), None);
         }
     }
 
-    pub fn mul(&mut self) -> Result<Node, String> {
-        let mut node = match self.unary() {
-            Ok(node) => node,
-            Err(msg) => {
-                return Err(msg);
-            }
+    /// `sizeof(型)`もしくは`sizeof 式`を解析する
+    /// サイズはコンパイル時に決まるので、被演算子の式のコードは生成せず数値のノードに置き換える
+    pub fn sizeof(&mut self) -> Result<Node, CompileError> {
+        // `(`の次が型名のときだけ`sizeof(型)`として扱う
+        // `sizeof ("abc")[1]`のように括弧の後ろに後置演算子が続くことがあるので、
+        // それ以外は括弧も含めて式として解析する
+        let ty = if self.lexer.peek_reserved() == Some(Reserved::LeftParen) && self.is_type_name(1)
+        {
+            self.lexer.consume(Reserved::LeftParen);
+
+            let ty = self.type_name()?;
+
+            self.lexer.expect(Reserved::RightParen)?;
+
+            ty
+        } else {
+            self.parse_expr(PREFIX_BINDING_POWER)?.get_type()
         };
 
-        loop {
-            if self.lexer.consume(Reserved::Asterisk) {
-                match self.unary() {
-                    Ok(unary) => {
-                        node =
-                            Node::new(NodeKind::Mul, Some(Box::new(node)), Some(Box::new(unary)));
-                    }
-                    Err(msg) => {
-                        return Err(msg);
-                    }
-                }
-            } else if self.lexer.consume(Reserved::Slash) {
-                match self.unary() {
-                    Ok(unary) => {
-                        node =
-                            Node::new(NodeKind::Div, Some(Box::new(node)), Some(Box::new(unary)));
-                    }
-                    Err(msg) => {
-                        return Err(msg);
-                    }
-                }
-            } else {
-                return Ok(node);
-            }
+        Ok(new_num(ty.size()))
+    }
+
+    /// `n`個先のトークンが型名の始まりかどうかを、トークンを読み進めずに判定する
+    pub fn is_type_name(&self, n: usize) -> bool {
+        matches!(
+            self.lexer.peek_nth(n),
+            Some(
+                Reserved::Int
+                    | Reserved::Char
+                    | Reserved::Short
+                    | Reserved::Long
+                    | Reserved::Signed
+                    | Reserved::Unsigned
+                    | Reserved::Bool
+                    | Reserved::Const
+            )
+        )
+    }
+
+    /// `int`や`char *`のような型名を解析する
+    /// type_name = declspec abstract_declarator
+    pub fn type_name(&mut self) -> Result<CType, CompileError> {
+        let base = self.declspec()?;
+
+        self.abstract_declarator(base)
+    }
+
+    /// 型指定子を解析する
+    /// declspec = ("signed" | "unsigned")? ("int" | "char" | "short" "int"? | "long" "long"? "int"?)
+    ///          | ("signed" | "unsigned")
+    ///          | "_Bool"
+    /// `long long`もintと同じ8バイトなので`long`と同じ型にする
+    /// 型指定子の前後の`const`は読み飛ばす
+    /// 書き換えられる左辺値がまだないので、型には記録しない
+    pub fn declspec(&mut self) -> Result<CType, CompileError> {
+        self.skip_const();
+
+        // `unsigned`や`signed`だけならintを表す
+        let sign = if self.lexer.consume(Reserved::Unsigned) {
+            Some(Reserved::Unsigned)
+        } else if self.lexer.consume(Reserved::Signed) {
+            Some(Reserved::Signed)
+        } else {
+            None
+        };
+
+        self.skip_const();
+
+        let ty = if self.lexer.consume(Reserved::Int) {
+            CType::Int
+        } else if self.lexer.consume(Reserved::Char) {
+            CType::Char
+        } else if self.lexer.consume(Reserved::Short) {
+            self.lexer.consume(Reserved::Int);
+            CType::Short
+        } else if self.lexer.consume(Reserved::Long) {
+            self.lexer.consume(Reserved::Long);
+            self.lexer.consume(Reserved::Int);
+            CType::Long
+        } else if sign.is_some() {
+            CType::Int
+        } else if self.lexer.consume(Reserved::Bool) {
+            CType::Bool
+        } else {
+            return Err(CompileError::parse(
+                self.lexer.peek_pos(),
+                "型名ではありません",
+            ));
+        };
+
+        self.skip_const();
+
+        if sign == Some(Reserved::Unsigned) {
+            return Ok(ty.to_unsigned());
         }
+
+        Ok(ty)
     }
 
-    pub fn unary(&mut self) -> Result<Node, String> {
-        if self.lexer.consume(Reserved::Plus) {
-            return self.primary();
+    /// 連続する`const`を読み飛ばす
+    fn skip_const(&mut self) {
+        while self.lexer.consume(Reserved::Const) {}
+    }
+
+    /// 変数名を持たない宣言子を解析し、`base`から組み立てた型を返す
+    /// abstract_declarator = "*"* ("(" abstract_declarator ")")? type_suffix
+    pub fn abstract_declarator(&mut self, base: CType) -> Result<CType, CompileError> {
+        let mut ty = base;
+
+        // `char * const`のように、`*`の後ろにも`const`を置ける
+        while self.lexer.consume(Reserved::Asterisk) {
+            ty = CType::Ptr(Box::new(ty));
+            self.skip_const();
         }
 
-        if self.lexer.consume(Reserved::Minus) {
-            match self.primary() {
-                Ok(node) => {
-                    return Ok(Node::new(
-                        NodeKind::Sub,
-                        Some(Box::new(Node::new(NodeKind::Num(0), None, None))),
-                        Some(Box::new(node)),
-                    ));
-                }
-                Err(msg) => {
-                    return Err(msg);
-                }
+        let pos = self.lexer.peek_pos();
+
+        if self.lexer.consume(Reserved::LeftParen) {
+            // 空の括弧は関数型の引数リストになる
+            if self.lexer.peek_reserved() == Some(Reserved::RightParen) {
+                return Err(CompileError::parse(pos, "関数型はサポートされていません"));
             }
+
+            // `int (*)[3]`のように、括弧の外側の配列が括弧の中身より先に型へかかる
+            // 括弧の中身を読み飛ばして後ろの配列を先に解析し、改めて括弧の中身を解析する
+            let start = self.lexer.clone();
+
+            self.abstract_declarator(CType::Int)?;
+            self.lexer.expect(Reserved::RightParen)?;
+
+            ty = self.type_suffix(ty)?;
+
+            let end = std::mem::replace(&mut self.lexer, start);
+
+            ty = self.abstract_declarator(ty)?;
+            self.lexer = end;
+
+            return Ok(ty);
+        }
+
+        self.type_suffix(ty)
+    }
+
+    /// 配列の要素数を解析し、`base`を要素とする配列型を返す
+    /// type_suffix = ("[" num "]" type_suffix)?
+    pub fn type_suffix(&mut self, base: CType) -> Result<CType, CompileError> {
+        // `int (*)()`のように宣言子の後ろに続く括弧も関数型になる
+        if self.lexer.peek_reserved() == Some(Reserved::LeftParen) {
+            return Err(CompileError::parse(
+                self.lexer.peek_pos(),
+                "関数型はサポートされていません",
+            ));
+        }
+
+        if !self.lexer.consume(Reserved::LeftBracket) {
+            return Ok(base);
+        }
+
+        let pos = self.lexer.peek_pos();
+        let len = match self.lexer.expect_number() {
+            Ok(len) if len >= 0 => len as usize,
+            _ => return Err(CompileError::parse(pos, "配列の要素数ではありません")),
+        };
+
+        self.lexer.expect(Reserved::RightBracket)?;
+
+        // `int[2][3]`は「intの3要素の配列」の2要素の配列
+        let base = self.type_suffix(base)?;
+
+        // サイズを数で表せない配列は要素数の位置でエラーにする
+        if CType::array_size(&base, len).is_none() {
+            return Err(CompileError::parse(pos, "配列が大きすぎます"));
         }
 
-        return self.primary();
+        Ok(CType::Array(Box::new(base), len))
     }
 
-    pub fn primary(&mut self) -> Result<Node, String> {
+    pub fn primary(&mut self) -> Result<Node, CompileError> {
+        // 閉じ括弧がないまま入力が終わった場合に示せるよう、開き括弧の位置を残しておく
+        let open = self.lexer.peek_pos();
+
         if self.lexer.consume(Reserved::LeftParen) {
-            let node = match self.expr() {
-                Ok(node) => node,
-                Err(msg) => {
-                    return Err(msg);
-                }
-            };
+            let node = self.expr()?;
 
-            if let Err(msg) = self.lexer.expect(Reserved::RightParen) {
-                return Err(msg);
+            // 入力の終わりを指しても分かりにくいので、対応する開き括弧を指す
+            // 別のトークンがある場合は、そのトークンを指すほうが分かりやすい
+            if self.lexer.at_eof() {
+                return Err(CompileError::parse(
+                    open,
+                    "対応する ) がありません, 開き括弧はここ",
+                ));
             }
 
+            self.lexer.expect(Reserved::RightParen)?;
+
             return Ok(node);
         }
 
@@ -234,6 +681,401 @@ impl<'a> Parser<'a> {
             return Ok(node);
         }
 
-        Err("予期しないトークンです".to_string())
+        if let Ok(bytes) = self.lexer.expect_string() {
+            let node = Node::new(NodeKind::Str(bytes), None, None);
+
+            return Ok(node);
+        }
+
+        Err(CompileError::parse(
+            self.lexer.peek_pos(),
+            "予期しないトークンです",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Node, NodeKind, Parser};
+    use crate::lexer::Lexer;
+
+    /// 構文木をS式の文字列に変換する
+    fn to_sexp(node: &Node) -> String {
+        let name = match node.get_kind() {
+            NodeKind::Num(num) => return num.to_string(),
+            NodeKind::Str(bytes) => return format!("{:?}", String::from_utf8_lossy(&bytes)),
+            NodeKind::Add => "+",
+            NodeKind::Sub => "-",
+            NodeKind::Mul => "*",
+            NodeKind::Div => "/",
+            NodeKind::Eq => "==",
+            NodeKind::Ne => "!=",
+            NodeKind::Lt => "<",
+            NodeKind::Le => "<=",
+            NodeKind::Shl => "<<",
+            NodeKind::Addr => return format!("(& {})", to_sexp(&node.get_lhs().unwrap())),
+            NodeKind::Deref => return format!("(* {})", to_sexp(&node.get_lhs().unwrap())),
+            NodeKind::Cast(ty) => {
+                return format!("(({}) {})", ty, to_sexp(&node.get_lhs().unwrap()))
+            }
+        };
+        let lhs = to_sexp(&node.get_lhs().unwrap());
+        let rhs = to_sexp(&node.get_rhs().unwrap());
+
+        format!("({} {} {})", name, lhs, rhs)
+    }
+
+    fn parse(input: &str) -> String {
+        try_parse(input).unwrap()
+    }
+
+    fn try_parse(input: &str) -> Result<String, (String, usize)> {
+        let input = input.to_string();
+        let mut lexer = Lexer::new(&input);
+
+        lexer.tokenize().ok().unwrap();
+
+        let mut parser = Parser::new(lexer);
+
+        // エラーはメッセージと位置の組で比べる
+        parser
+            .program()
+            .map(|node| to_sexp(&node))
+            .map_err(|err| (err.to_string(), err.span().unwrap().start))
+    }
+
+    #[test]
+    fn expr() {
+        // 再帰下降構文解析で組み立てていた構文木と同じになることを確かめる
+        // 単項演算子の連続は単項演算子の被演算子として解析する
+        let cases = [
+            ("42", "42"),
+            ("1+2", "(+ 1 2)"),
+            ("5+20-4", "(- (+ 5 20) 4)"),
+            ("5+6*7", "(+ 5 (* 6 7))"),
+            ("5*(9-6)", "(* 5 (- 9 6))"),
+            ("(3+5)/2", "(/ (+ 3 5) 2)"),
+            ("-10+20", "(+ (- 0 10) 20)"),
+            ("+3*-4", "(* 3 (- 0 4))"),
+            ("- - 3", "(- 0 (- 0 3))"),
+            ("-+3", "(- 0 3)"),
+            ("1-2-3", "(- (- 1 2) 3)"),
+            ("8/4/2", "(/ (/ 8 4) 2)"),
+            ("1*2+3*4", "(+ (* 1 2) (* 3 4))"),
+            (
+                "4+5*2-9/3 == 11+0-0",
+                "(== (- (+ 4 (* 5 2)) (/ 9 3)) (- (+ 11 0) 0))",
+            ),
+            ("1 == 2 != 3", "(!= (== 1 2) 3)"),
+            ("1 < 2 <= 3", "(<= (< 1 2) 3)"),
+            ("3 > 2 > 1", "(< 1 (< 2 3))"),
+            ("1 >= 2 + 3", "(<= (+ 2 3) 1)"),
+            ("1 + 2 < 3 == 4 > 5 * 6", "(== (< (+ 1 2) 3) (< (* 5 6) 4))"),
+            ("sizeof(int) * 2", "(* 8 2)"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(expected, parse(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn greater_than_chain() {
+        // `>`と`>=`の連鎖も左結合になり、それまでの式全体が右辺に移る
+        let cases = [
+            ("3 > 2 > 1", "(< 1 (< 2 3))"),
+            ("3 >= 2 >= 1", "(<= 1 (<= 2 3))"),
+            ("4 > 3 > 2 > 1", "(< 1 (< 2 (< 3 4)))"),
+            ("1 < 2 > 0", "(< 0 (< 1 2))"),
+            ("3 > 2 < 1", "(< (< 2 3) 1)"),
+            ("3 > 2 >= 1 > 0", "(< 0 (<= 1 (< 2 3)))"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(expected, parse(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn display() {
+        let display = |input: &str| {
+            let input = input.to_string();
+            let mut lexer = Lexer::new(&input);
+
+            lexer.tokenize().ok().unwrap();
+
+            Parser::new(lexer).program().ok().unwrap().to_string()
+        };
+
+        assert_eq!("(mul (add (num 1) (num 2)) (num 3))", display("(1+2)*3"));
+        assert_eq!("(add (num 1) (mul (num 2) (num 3)))", display("1+2*3"));
+        assert_eq!(
+            r#"(deref (add (str "ab") (mul (num 1) (num 1))))"#,
+            display(r#""ab"[1]"#)
+        );
+    }
+
+    #[test]
+    fn addr_and_deref() {
+        assert_eq!(r#"(& (* "abc"))"#, parse(r#"&*"abc""#));
+        assert_eq!(r#"(+ (* "abc") 1)"#, parse(r#"*"abc" + 1"#));
+        assert_eq!(
+            ("左辺値ではないのでアドレスを取れません".to_string(), 4),
+            try_parse("1 + &(1+1)").unwrap_err()
+        );
+        assert_eq!(
+            ("ポインタではない値を参照しています".to_string(), 0),
+            try_parse("*1").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn pointer_arithmetic() {
+        assert_eq!(r#"(+ "abc" (* 1 1))"#, parse(r#""abc" + 1"#));
+        assert_eq!(r#"(+ "abc" (* 2 1))"#, parse(r#"2 + "abc""#));
+        assert_eq!(r#"(- "abc" (* 1 1))"#, parse(r#""abc" - 1"#));
+        assert_eq!(r#"(/ (- "abc" "a") 1)"#, parse(r#""abc" - "a""#));
+        assert_eq!(
+            ("ポインタ同士は足せません".to_string(), 6),
+            try_parse(r#""abc" + "a""#).unwrap_err()
+        );
+        assert_eq!(
+            ("整数からポインタは引けません".to_string(), 2),
+            try_parse(r#"1 - "a""#).unwrap_err()
+        );
+        // ポインタは掛けたり割ったりできず、演算子の位置でエラーになる
+        assert_eq!(
+            ("ポインタは掛け算できません".to_string(), 4),
+            try_parse(r#""a" * 2"#).unwrap_err()
+        );
+        assert_eq!(
+            ("ポインタは掛け算できません".to_string(), 2),
+            try_parse(r#"2 * "a""#).unwrap_err()
+        );
+        assert_eq!(
+            ("ポインタは掛け算できません".to_string(), 4),
+            try_parse(r#""a" * "a""#).unwrap_err()
+        );
+        assert_eq!(
+            ("ポインタは割り算できません".to_string(), 4),
+            try_parse(r#""a" / 2"#).unwrap_err()
+        );
+        assert_eq!(r#"(* (* "a") 2)"#, parse(r#"*"a" * 2"#));
+    }
+
+    #[test]
+    fn pointer_comparison() {
+        assert_eq!(r#"(< "a" "b")"#, parse(r#""a" < "b""#));
+        assert_eq!(r#"(== "a" 0)"#, parse(r#""a" == 0"#));
+        assert_eq!(r#"(!= 0 "a")"#, parse(r#"0 != "a""#));
+        // 0以外の整数や、`==`と`!=`以外の比較はエラーになる
+        assert_eq!(
+            ("ポインタと整数は比較できません".to_string(), 4),
+            try_parse(r#""a" < 1"#).unwrap_err()
+        );
+        assert_eq!(
+            ("ポインタと整数は比較できません".to_string(), 2),
+            try_parse(r#"1 > "a""#).unwrap_err()
+        );
+        assert_eq!(
+            ("ポインタと整数は比較できません".to_string(), 4),
+            try_parse(r#""a" == 1"#).unwrap_err()
+        );
+        assert_eq!(
+            ("ポインタと整数は比較できません".to_string(), 4),
+            try_parse(r#""a" <= 0"#).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn subscript() {
+        assert_eq!(r#"(* (+ "abc" (* 1 1)))"#, parse(r#""abc"[1]"#));
+        assert_eq!(r#"(* (+ "abc" (* 1 1)))"#, parse(r#"1["abc"]"#));
+        assert_eq!(r#"(* (+ "abc" (* (- 2 1) 1)))"#, parse(r#""abc"[2 - 1]"#));
+        assert_eq!(
+            Err(("ポインタではない値を参照しています".to_string(), 1)),
+            try_parse("1[2]")
+        );
+    }
+
+    #[test]
+    fn sizeof() {
+        assert_eq!("8", parse("sizeof(int)"));
+        assert_eq!("8", parse("sizeof(int *)"));
+        assert_eq!("1", parse("sizeof(char)"));
+        assert_eq!("1", parse("sizeof(_Bool)"));
+        assert_eq!("8", parse("sizeof(_Bool *)"));
+        assert_eq!("2", parse("sizeof(short)"));
+        assert_eq!("2", parse("sizeof(short int)"));
+        assert_eq!("6", parse("sizeof(const short[3])"));
+        assert_eq!("8", parse("sizeof(long)"));
+        assert_eq!("8", parse("sizeof(long int)"));
+        assert_eq!("8", parse("sizeof(long long)"));
+        assert_eq!("8", parse("sizeof(long long int *)"));
+        assert_eq!("8", parse("sizeof(unsigned)"));
+        assert_eq!("1", parse("sizeof(unsigned char)"));
+        assert_eq!("1", parse("sizeof(signed char)"));
+        assert_eq!("2", parse("sizeof(unsigned short int)"));
+        assert_eq!("8", parse("sizeof(const unsigned long long)"));
+        assert_eq!("8", parse("sizeof(signed)"));
+        assert_eq!("8", parse("sizeof((unsigned char)1 + (unsigned char)1)"));
+        // `const`は型の前後や`*`の後ろに置ける
+        assert_eq!("8", parse("sizeof(const int)"));
+        assert_eq!("1", parse("sizeof(char const)"));
+        assert_eq!("8", parse("sizeof(const char * const)"));
+        assert_eq!("8", parse("sizeof(const const int * const * const)"));
+        assert_eq!("24", parse("sizeof(const int *const [3])"));
+        assert_eq!(
+            ("型名ではありません".to_string(), 12),
+            try_parse("sizeof(const)").unwrap_err()
+        );
+        assert_eq!("8", parse("sizeof(char (*))"));
+        assert_eq!("(+ 8 1)", parse("sizeof (1) + 1"));
+        assert_eq!("1", parse(r#"sizeof(*"abc")"#));
+        assert_eq!("80", parse("sizeof(int[10])"));
+        assert_eq!("48", parse("sizeof(int[2][3])"));
+        assert_eq!("24", parse("sizeof(char *[3])"));
+        assert_eq!("8", parse("sizeof(int (*)[3])"));
+        assert_eq!(
+            "9223372036854775800",
+            parse("sizeof(int[1152921504606846975])")
+        );
+        // 関数型は括弧の位置でエラーになる
+        assert_eq!(
+            ("関数型はサポートされていません".to_string(), 11),
+            try_parse("sizeof(int ())").unwrap_err()
+        );
+        assert_eq!(
+            ("関数型はサポートされていません".to_string(), 14),
+            try_parse("sizeof(int (*)())").unwrap_err()
+        );
+        assert_eq!(
+            ("関数型はサポートされていません".to_string(), 6),
+            try_parse("(char ())1").unwrap_err()
+        );
+        assert_eq!(
+            ("配列が大きすぎます".to_string(), 11),
+            try_parse("sizeof(int[2305843009213693952])").unwrap_err()
+        );
+        assert_eq!(
+            ("配列が大きすぎます".to_string(), 12),
+            try_parse("sizeof(char[4294967296][4294967296])").unwrap_err()
+        );
+        assert_eq!("4", parse(r#"sizeof "abc""#));
+        assert_eq!("8", parse(r#"sizeof("abc" + 1)"#));
+        assert_eq!("1", parse(r#"sizeof ("abc")[1]"#));
+    }
+
+    #[test]
+    fn cast() {
+        assert_eq!("((char) 257)", parse("(char)257"));
+        assert_eq!("((short) 65537)", parse("(short)65537"));
+        assert_eq!("((long) ((short) 1))", parse("(long)(short int)1"));
+        assert_eq!("((unsigned int) (- 0 1))", parse("(unsigned)-1"));
+        assert_eq!("((unsigned char) 1)", parse("(unsigned char)1"));
+        assert_eq!("((char) 1)", parse("(signed char)1"));
+        assert_eq!("((int) ((char) (- 0 1)))", parse("(int)(char)-1"));
+        // キャストは単項演算子と同じ強さで結合する
+        assert_eq!("(+ ((char) 1) 2)", parse("(char)1 + 2"));
+        assert_eq!("((char) (+ 1 2))", parse("(char)(1 + 2)"));
+        assert_eq!("(+ ((_Bool) 5) ((_Bool) 5))", parse("(_Bool)5 + (_Bool)5"));
+        assert_eq!(r#"((char*) "a")"#, parse(r#"(const char *)"a""#));
+        // ポインタへのキャストでは、加算の倍率がキャスト先の型で決まる
+        assert_eq!(r#"(+ ((int*) "abc") (* 1 8))"#, parse(r#"(int*)"abc" + 1"#));
+        assert_eq!("1", parse("sizeof((char)1)"));
+        assert_eq!(
+            ("配列型にはキャストできません".to_string(), 0),
+            try_parse("(int[2])1").unwrap_err()
+        );
+        assert_eq!(
+            ("ポインタではない値を参照しています".to_string(), 0),
+            try_parse(r#"*(int)"abc""#).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn error_position() {
+        // 閉じ括弧が足りないまま入力が終わる場合は、対応する開き括弧を指す
+        assert_eq!(
+            ("対応する ) がありません, 開き括弧はここ".to_string(), 0),
+            try_parse("(1 + 2").unwrap_err()
+        );
+        assert_eq!(
+            ("対応する ) がありません, 開き括弧はここ".to_string(), 0),
+            try_parse("((1 + 2)").unwrap_err()
+        );
+        assert_eq!(
+            ("対応する ) がありません, 開き括弧はここ".to_string(), 17),
+            try_parse("(1 + (2 * (3)) - (4").unwrap_err()
+        );
+        assert_eq!(
+            ("対応する ) がありません, 開き括弧はここ".to_string(), 1),
+            try_parse("((1 + (2 * 3)").unwrap_err()
+        );
+        // 閉じ括弧の代わりに別のトークンがある場合はそのトークンを指す
+        assert_eq!(
+            (")ではありません".to_string(), 9),
+            try_parse("((1 + 2) 3").unwrap_err()
+        );
+        assert_eq!(
+            ("予期しないトークンです".to_string(), 4),
+            try_parse("1 + )").unwrap_err()
+        );
+        // 空のプログラムは入力の先頭を指す
+        assert_eq!(
+            ("空のプログラムです".to_string(), 0),
+            try_parse("").unwrap_err()
+        );
+        assert_eq!(
+            ("空のプログラムです".to_string(), 0),
+            try_parse("   ").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn decrement() {
+        // `- -3`は符号の反転を2回行う
+        assert_eq!(Ok("(- 0 (- 0 3))".to_string()), try_parse("- -3"));
+
+        // `--`はデクリメントなので、左辺値でなければエラーになる
+        assert_eq!(
+            ("左辺値ではないのでデクリメントできません".to_string(), 0),
+            try_parse("--3").unwrap_err()
+        );
+        assert_eq!(
+            ("デクリメントはまだサポートされていません".to_string(), 0),
+            try_parse(r#"--*"a""#).unwrap_err()
+        );
+        assert_eq!(
+            ("デクリメントはまだサポートされていません".to_string(), 0),
+            try_parse("--*(char*)8").unwrap_err()
+        );
+        // 後置のデクリメントはないので、`1--2`の`--`は余分なトークンになる
+        assert_eq!(
+            ("余分なトークン `--` があります".to_string(), 1),
+            try_parse("1--2").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn trailing_tokens() {
+        // 式の後に残ったトークンは、読み飛ばさずにその位置でエラーにする
+        let cases = [
+            ("1+2)", "余分なトークン `)` があります", 3),
+            ("1+2 3", "余分なトークン `3` があります", 4),
+            ("1 2", "余分なトークン `2` があります", 2),
+            ("1 + 2 )", "余分なトークン `)` があります", 6),
+            ("(1)(2)", "余分なトークン `(` があります", 3),
+            (r#"1 "a""#, r#"余分なトークン `"a"` があります"#, 2),
+        ];
+
+        for (input, message, pos) in cases {
+            assert_eq!(
+                (message.to_string(), pos),
+                try_parse(input).unwrap_err(),
+                "{}",
+                input
+            );
+        }
     }
 }
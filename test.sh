@@ -3,35 +3,126 @@ assert(){
   expected="$1"
   input="$2"
 
-  ./target/debug/9cc "$input" > ./test/tmp.s
-  cc -o ./test/tmp ./test/tmp.s
-  ./test/tmp
-  
-  actual="$?"
+  for syntax in "" --att-syntax; do
+    ./target/debug/9cc $syntax "$input" > ./test/tmp.s
+    cc -o ./test/tmp ./test/tmp.s
+    ./test/tmp
 
-  if [  "$actual" = "$expected"  ]; then
-    echo "$input => $actual"
-  else
-    echo "$input => $expected, but got $actual"
-    exit 1
-  fi
+    actual="$?"
+
+    if [  "$actual" = "$expected"  ]; then
+      echo "$input $syntax => $actual"
+    else
+      echo "$input $syntax => $expected, but got $actual"
+      exit 1
+    fi
+  done
 }
 
 mkdir test
 
 assert 0 0
 assert 42 42
+assert 100 '1_00'
+assert 1 '1_000_000 == 1000000'
+assert 1 '65536*65536 < 65536*65536+1'
 assert 21 '5+20-4'
 assert 41 ' 12 + 34 - 5 '
 assert 47 '5+6*7'
 assert 15 '5*(9-6)'
 assert 4 '(3+5)/2'
 assert 10 '-10+20'
+assert 3 '- - 3'
+assert 3 '-(-3)'
+assert 3 '- -+3'
+assert 5 '1- -4'
 assert 1 '4+5*2-9/3 == 11+0-0'
 assert 0 '4+5*( 2-9 )/3 == ( 11 )'
 assert 1 '12<13'
 assert 1 '12<=12'
 assert 1 '12>=12'
 assert 1 '13>12'
+assert 0 '3>2>1'
+assert 1 '3>2>0'
+assert 1 '3>=2>=1'
+assert 1 '1<2>0'
+assert 1 '(char)257'
+assert 255 '(int)(char)-1'
+assert 1 '(char)-255'
+assert 105 '*((char*)((int*)"abcdefghi" + 1))'
+assert 1 'sizeof((char)1)'
+assert 1 '(_Bool)5'
+assert 0 '(_Bool)0'
+assert 2 '(_Bool)256 + (_Bool)-1'
+assert 97 '**(char(*)[3])"abc"'
+assert 2 'sizeof(short)'
+assert 8 'sizeof(long)'
+assert 1 '(short)65537'
+assert 1 '*(short*)"\xff\xff" == -1'
+assert 1 '(unsigned)-1 > 0'
+assert 255 '(unsigned char)-1'
+assert 1 '(unsigned)-1 / 4294967296 == 4294967295'
+assert 1 'sizeof(unsigned short) == sizeof(short)'
+assert 1 'sizeof(_Bool)'
+assert 4 'sizeof(_Bool[4])'
+assert 98 '*((const char * const)"abc" + 1)'
+assert 8 'sizeof(int const)'
+assert 8 'sizeof(int)'
+assert 8 'sizeof(int*)'
+assert 8 'sizeof(int **)'
+assert 8 'sizeof(1+2)'
+assert 1 'sizeof(char)'
+assert 8 'sizeof(char*)'
+assert 8 'sizeof(char (*))'
+assert 80 'sizeof(int[10])'
+assert 48 'sizeof(int[2][3])'
+assert 8 'sizeof(int (*)[3])'
+assert 4 'sizeof "abc"'
+assert 8 'sizeof(int (**))'
+assert 1 'sizeof(int) == 8'
+assert 10 'sizeof(int*) + 2'
+assert 8 'sizeof 1'
+assert 9 'sizeof 1 + 1'
+assert 9 'sizeof (1) + 1'
+assert 1 'sizeof *"abc"'
+assert 8 'sizeof(sizeof 1)'
+assert 8 'sizeof -1'
+assert 65 "'A'"
+assert 10 "'\\n'"
+assert 66 "'A' + 1"
+assert 65 "'\\x41'"
+assert 8 "'\\10'"
+assert 0 "'\\0'"
+assert 97 "'a'"
+assert 25 "'z' - 'a'"
+assert 92 "'\\\\'"
+assert 39 "'\\''"
+assert 1 "'\\xff' == *\"\\xff\""
+assert 1 "'\\200' < 0"
+assert 1 '"abc" == "abc"'
+assert 0 '"abc" == "abd"'
+assert 65 '*"A"'
+assert 67 '*"BC" + 1'
+assert 1 '&*"abc" == "abc"'
+assert 1 'sizeof(*"abc")'
+assert 8 'sizeof(&*"abc")'
+assert 98 '"abc"[1]'
+assert 98 '1["abc"]'
+assert 99 '"abc"[1 + 1] - 0'
+assert 0 '"abc"[3]'
+assert 1 'sizeof ("abc")[1]'
+assert 196 '"abc"[1] + 1["abc"]'
+assert 10 '*"\n"'
+assert 9 '*"\t"'
+assert 13 '*"\r"'
+assert 7 '*"\a"'
+assert 7 "'\\a'"
+assert 92 '*"\\"'
+assert 34 '*"\""'
+assert 98 '*("abc" + 1)'
+assert 99 '*(2 + "abc")'
+assert 98 '*("abc" + 2 - 1)'
+assert 2 '("abc" + 2) - "abc"'
+assert 8 'sizeof("abc" + 1)'
 
 echo OK
\ No newline at end of file
@@ -0,0 +1,231 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// コンパイラのバイナリを引数付きで実行する
+fn run(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_9cc"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn emit_tokens() {
+    // `--emit-tokens`は`--emit=tokens`の別名で、同じく標準出力に書く
+    let output = run(&["--emit-tokens", "1+2"]);
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, run(&["--emit=tokens", "1+2"]).stdout);
+    assert_eq!(
+        concat!(
+            r#"[{"kind":"Num","lexeme":"1","span":[0,1]},"#,
+            r#"{"kind":"Reserved","lexeme":"+","span":[1,2]},"#,
+            r#"{"kind":"Num","lexeme":"2","span":[2,3]},"#,
+            r#"{"kind":"Eof","lexeme":"","span":[3,3]}]"#,
+            "\n"
+        ),
+        String::from_utf8(output.stdout).unwrap()
+    );
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn emit_ast() {
+    let output = run(&["--emit-ast", "(1+2)*3"]);
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, run(&["--emit=ast", "(1+2)*3"]).stdout);
+    assert_eq!(
+        "(mul (add (num 1) (num 2)) (num 3))\n",
+        String::from_utf8(output.stdout).unwrap()
+    );
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn output_file() {
+    let path = std::env::temp_dir().join(format!("9cc-output-{}.s", std::process::id()));
+    let path_str = path.to_str().unwrap();
+
+    for flag in ["-o", "--output"] {
+        let output = run(&[flag, path_str, "1+2"]);
+
+        assert!(output.status.success());
+        assert!(output.stdout.is_empty());
+
+        // ファイルには標準出力に書く場合と同じアセンブリが書かれる
+        let expected = run(&["1+2"]).stdout;
+
+        assert_eq!(expected, std::fs::read(&path).unwrap());
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn output_file_error() {
+    let output = run(&["-o", "/nonexistent/out.s", "1"]);
+
+    assert_eq!(Some(1), output.status.code());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("出力ファイル/nonexistent/out.sを開けません"));
+
+    let output = run(&["1", "-o"]);
+
+    assert_eq!(Some(1), output.status.code());
+}
+
+#[test]
+fn target_aarch64() {
+    let output = run(&["--target", "aarch64-linux-gnu", r#""ab"[1] + 2"#]);
+
+    assert!(output.status.success());
+
+    let asm = String::from_utf8(output.stdout).unwrap();
+
+    assert!(asm.contains("  ldrsb x0, [x0]\n"));
+    assert!(asm.contains("  add x0, x0, x1\n"));
+    assert!(!asm.contains("rax"));
+
+    // クロスアセンブラがあれば、アセンブルできることも確かめる
+    let path = std::env::temp_dir().join(format!("9cc-aarch64-{}.s", std::process::id()));
+
+    std::fs::write(&path, &asm).unwrap();
+
+    if let Ok(status) = Command::new("aarch64-linux-gnu-as")
+        .arg(&path)
+        .arg("-o")
+        .arg(path.with_extension("o"))
+        .status()
+    {
+        assert!(status.success());
+        std::fs::remove_file(path.with_extension("o")).unwrap();
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn target_arm64() {
+    let output = run(&["--target=arm64", "1+2"]);
+
+    assert!(output.status.success());
+
+    let asm = String::from_utf8(output.stdout).unwrap();
+
+    assert!(asm.contains("  stp x29, x30, [sp, #-16]!\n"));
+    assert!(asm.contains("  mov x0, #3\n"));
+    assert!(asm.contains("  ldp x29, x30, [sp], #16\n"));
+    assert!(!asm.contains("rax"));
+
+    // 比較もARMの命令で書かれる
+    let asm = String::from_utf8(run(&["--target=arm64", "1<2"]).stdout).unwrap();
+
+    assert!(asm.contains("  cmp x0, x1\n  cset x0, lt\n"));
+
+    // 既定はx86-64
+    assert_eq!(
+        run(&["1+2"]).stdout,
+        run(&["--target=x86_64", "1+2"]).stdout
+    );
+}
+
+#[test]
+fn unknown_target() {
+    let output = run(&["--target", "mips-linux-gnu", "1"]);
+
+    assert_eq!(Some(1), output.status.code());
+
+    let output = run(&["--target=mips", "1"]);
+
+    assert_eq!(Some(1), output.status.code());
+}
+
+#[test]
+fn wrong_argument_count() {
+    let output = run(&[]);
+
+    assert_eq!(Some(1), output.status.code());
+
+    let output = run(&["1", "2"]);
+
+    assert_eq!(Some(1), output.status.code());
+}
+
+#[test]
+fn emit_stage() {
+    // `--emit=tokens`はトークン列だけを標準出力に書き、アセンブリは書かない
+    let output = run(&["1+2", "--emit=tokens"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.starts_with(r#"[{"kind":"Num","lexeme":"1","span":[0,1]},"#));
+    assert!(stdout.ends_with(concat!(r#"{"kind":"Eof","lexeme":"","span":[3,3]}]"#, "\n")));
+    assert!(!stdout.contains(".intel_syntax"));
+
+    let output = run(&["--emit=ast", "(1+2)*3"]);
+
+    assert_eq!(
+        "(mul (add (num 1) (num 2)) (num 3))\n",
+        String::from_utf8(output.stdout).unwrap()
+    );
+
+    // 既定はアセンブリ
+    assert_eq!(run(&["1+2"]).stdout, run(&["--emit=asm", "1+2"]).stdout);
+}
+
+#[test]
+fn unknown_option() {
+    for arg in ["--emit=ir", "--dump-tokens"] {
+        let output = run(&[arg, "1"]);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+
+        assert_eq!(Some(1), output.status.code());
+        assert!(stderr.contains("使い方: 9cc"), "{}", arg);
+    }
+
+    // 英字の続かない`--`はプログラムとして扱う
+    let output = run(&["--3"]);
+
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("デクリメント"));
+}
+
+#[test]
+fn repl() {
+    if Command::new("cc").arg("--version").output().is_err() {
+        eprintln!("ccが見つからないので実行を省略します");
+        return;
+    }
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_9cc"))
+        .arg("--repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // 空行は読み飛ばし、エラーがあっても次の式を評価する
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"1+2\n(1+2)*3\n\n1 2\n2<3\n")
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        "> 3\n> 9\n> > > 1\n> \n",
+        String::from_utf8(output.stdout).unwrap()
+    );
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("余分なトークン `2` があります"));
+}
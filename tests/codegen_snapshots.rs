@@ -0,0 +1,61 @@
+use c_compiler_edu::Compiler;
+
+/// x86-64向けにIntel記法でコンパイルしたアセンブリ
+fn compile(source: &str) -> String {
+    Compiler::new(source).compile().unwrap()
+}
+
+#[test]
+fn arithmetic() {
+    // 定数同士の演算は畳み込まれるので、文字列リテラルから読んだ値で計算させる
+    insta::assert_snapshot!(compile(r#"*"a" * 3 + 4 / *"b" - 5"#));
+}
+
+#[test]
+fn constant_folding() {
+    insta::assert_snapshot!(compile("2 * 3 + 4 * 5"));
+}
+
+#[test]
+fn comparison_eq() {
+    insta::assert_snapshot!(compile("1 == 2"));
+}
+
+#[test]
+fn comparison_ne() {
+    insta::assert_snapshot!(compile("1 != 2"));
+}
+
+#[test]
+fn comparison_lt() {
+    insta::assert_snapshot!(compile("1 < 2"));
+}
+
+#[test]
+fn comparison_le() {
+    insta::assert_snapshot!(compile("1 <= 2"));
+}
+
+#[test]
+fn comparison_gt() {
+    insta::assert_snapshot!(compile("1 > 2"));
+}
+
+#[test]
+fn unary_minus() {
+    insta::assert_snapshot!(compile(r#"-*"a""#));
+}
+
+#[test]
+fn parentheses() {
+    insta::assert_snapshot!(compile(r#"(*"a" + 1) * (*"b" - 2)"#));
+}
+
+#[test]
+fn att_syntax() {
+    let mut compiler = Compiler::new(r#"*"a" < 2"#);
+
+    compiler.set_syntax(c_compiler_edu::codegen::SyntaxMode::Att);
+
+    insta::assert_snapshot!(compiler.compile().unwrap());
+}
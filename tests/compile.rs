@@ -0,0 +1,63 @@
+use c_compiler_edu::{error::Span, lexer::Lexer, CompileError, Compiler};
+
+#[test]
+fn compile_arithmetic() {
+    let expected = "\
+.intel_syntax noprefix
+.globl main
+main:
+  push rbp
+  mov rbp, rsp
+  sub rsp, 0
+  mov rax, 3
+.L.return.main:
+  mov rsp, rbp
+  pop rbp
+  ret
+";
+
+    assert_eq!(Ok(expected.to_string()), Compiler::new("1+2").compile());
+}
+
+#[test]
+fn compile_function() {
+    assert_eq!(
+        Compiler::new("5*(9-6)").compile(),
+        c_compiler_edu::compile("5*(9-6)")
+    );
+    assert!(c_compiler_edu::compile("5*(9-6)")
+        .unwrap()
+        .contains("  mov rax, 15\n"));
+    assert_eq!(
+        Some(CompileError::parse(0, "空のプログラムです")),
+        c_compiler_edu::compile("").err()
+    );
+}
+
+#[test]
+fn compile_string_literal() {
+    let asm = Compiler::new(r#""ab"[1]"#).compile().unwrap();
+
+    assert!(asm.contains("  lea rax, [rip + .L.str.0]\n"));
+    assert!(asm.ends_with(".section .rodata\n.L.str.0:\n  .byte 97, 98, 0\n"));
+}
+
+#[test]
+fn compile_error() {
+    let source = "1 + )".to_string();
+    let err = Compiler::new(&source).compile().unwrap_err();
+
+    assert_eq!(CompileError::parse(4, "予期しないトークンです"), err);
+    assert_eq!(
+        "1 + )\n    ^ 予期しないトークンです",
+        Lexer::new(&source).error_at(&err)
+    );
+}
+
+#[test]
+fn compile_lex_error() {
+    let err = Compiler::new("1 + 'ab'").compile().unwrap_err();
+
+    assert!(matches!(err, CompileError::LexError { .. }));
+    assert_eq!(Some(Span::at(4)), err.span());
+}
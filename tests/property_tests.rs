@@ -0,0 +1,56 @@
+use c_compiler_edu::lexer::{Lexer, Reserved, TokenKind};
+use proptest::prelude::*;
+
+/// 入力を字句解析し、EOFを除いたトークンの種類と文字列の組を返す
+fn tokenize(input: &str) -> Result<Vec<(TokenKind, String)>, String> {
+    let input = input.to_string();
+    let tokens = Lexer::new(&input)
+        .tokenize()
+        .map_err(|err| err.to_string())?;
+
+    let (eof, tokens) = tokens.split_last().unwrap();
+
+    assert!(matches!(eof.get_kind(), TokenKind::Eof));
+    assert_eq!(input.len(), eof.get_pos());
+
+    Ok(tokens
+        .iter()
+        .map(|token| (token.get_kind(), token.get_lexeme().to_string()))
+        .collect())
+}
+
+proptest! {
+    #[test]
+    fn non_negative_number(n in 0..=isize::MAX) {
+        let tokens = tokenize(&n.to_string()).unwrap();
+
+        prop_assert_eq!(1, tokens.len());
+        prop_assert!(matches!(tokens[0].0, TokenKind::Num(num) if num == n));
+    }
+
+    #[test]
+    fn negative_number(n in (isize::MIN + 1)..0) {
+        // 負の数は単項演算子の`-`と絶対値の数に分かれる
+        let tokens = tokenize(&n.to_string()).unwrap();
+
+        prop_assert_eq!(2, tokens.len());
+        prop_assert!(matches!(tokens[0].0, TokenKind::Reserved(Reserved::Minus)));
+        prop_assert!(matches!(tokens[1].0, TokenKind::Num(num) if num == -n));
+    }
+
+    #[test]
+    fn whitespace_only(input in "[ \t\n]*") {
+        prop_assert!(tokenize(&input).unwrap().is_empty());
+    }
+
+    #[test]
+    fn operator_sequence(input in r"[-+*/&()\[\]=!<> 0-9]{0,32}") {
+        // エラーになってもよいが、パニックせず、トークンは入力の文字列を順に覆う
+        if let Ok(tokens) = tokenize(&input) {
+            let lexemes: String = tokens.iter().map(|(_, lexeme)| lexeme.as_str()).collect();
+            let expected: String = input.chars().filter(|c| !c.is_whitespace()).collect();
+
+            prop_assert_eq!(expected, lexemes);
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    env, fs,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use c_compiler_edu::compile;
+
+/// ソースコードをコンパイルし、アセンブル・リンクして実行したときの終了コードを返す
+/// Cコンパイラが見つからない場合はNoneを返す
+fn run(name: &str, source: &str) -> Option<i32> {
+    let dir = env::temp_dir().join(format!("9cc-run-{}-{}", std::process::id(), name));
+    let asm_path = dir.join("tmp.s");
+    let exe_path: PathBuf = dir.join("tmp");
+
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(&asm_path, compile(source).unwrap()).unwrap();
+
+    let status = Command::new("cc")
+        .arg("-o")
+        .arg(&exe_path)
+        .arg(&asm_path)
+        .stderr(Stdio::null())
+        .status();
+
+    let code = match status {
+        Ok(status) => {
+            assert!(status.success(), "{}: アセンブルに失敗しました", source);
+
+            Command::new(&exe_path).status().unwrap().code()
+        }
+        Err(_) => None,
+    };
+
+    fs::remove_dir_all(&dir).unwrap();
+
+    code
+}
+
+/// `(ソースコード, 期待する終了コード)`の組をすべて実行して確かめる
+fn check(name: &str, cases: &[(&str, i32)]) {
+    for (i, (source, expected)) in cases.iter().enumerate() {
+        match run(&format!("{}-{}", name, i), source) {
+            Some(code) => assert_eq!(*expected, code, "{}", source),
+            None => {
+                eprintln!("ccが見つからないので実行を省略します");
+                return;
+            }
+        }
+    }
+}
+
+#[test]
+fn arithmetic() {
+    check(
+        "arithmetic",
+        &[
+            ("0", 0),
+            ("42", 42),
+            ("5+20-4", 21),
+            ("5+6*7", 47),
+            ("(3+5)/2", 4),
+            ("-10+20", 10),
+            ("1_000 / 10", 100),
+        ],
+    );
+}
+
+#[test]
+fn large_constant() {
+    // 畳み込んだ結果が32ビットに収まらなくても、そのまま使える
+    check(
+        "large_constant",
+        &[
+            ("65536*65536 < 65536*65536+1", 1),
+            ("65536*65536 == 4294967296", 1),
+            ("-65536*65536 < 0", 1),
+            (r#"*"\x03" + 4294967296 - 4294967296"#, 3),
+        ],
+    );
+}
+
+#[test]
+fn comparison() {
+    check(
+        "comparison",
+        &[
+            ("1 == 1", 1),
+            ("1 != 1", 0),
+            ("1 < 2", 1),
+            ("2 <= 1", 0),
+            ("2 > 1", 1),
+            ("1 >= 2", 0),
+        ],
+    );
+}
+
+#[test]
+fn strength_reduction() {
+    check(
+        "strength_reduction",
+        &[
+            (r#"*"\x03" * 1"#, 3),
+            (r#"*"\x03" * 2"#, 6),
+            (r#"*"\x03" * 4"#, 12),
+            (r#"8 * *"\x03""#, 24),
+            (r#"*"\x03" * 16"#, 48),
+            (r#"-*"\x03" * 4 + 20"#, 8),
+            (r#"("abcd" + 2) - "abcd""#, 2),
+        ],
+    );
+}
+
+#[test]
+fn parentheses() {
+    check(
+        "parentheses",
+        &[
+            ("5*(9-6)", 15),
+            ("((((1))))", 1),
+            ("(1 + 2) * (3 + 4)", 21),
+            ("-(3 + 5) + 10", 2),
+        ],
+    );
+}
+
+#[test]
+fn cast() {
+    check(
+        "cast",
+        &[
+            ("(char)257", 1),
+            ("(int)(char)-1 + 2", 1),
+            ("(char)127 + (char)1", 128),
+            (r#"*((char*)((int*)"abcdefghi" + 1))"#, 105),
+            ("(_Bool)5", 1),
+            ("(_Bool)0", 0),
+            ("(_Bool)256 + (_Bool)-1", 2),
+            // shortへの変換は下位2バイトを残して符号拡張する
+            ("(short)65537", 1),
+            ("(short)32768 < 0", 1),
+            ("(int)(short)65535 + 2", 1),
+            ("(long)(short)-1 == -1", 1),
+            (r#"*(short*)"\x01\x02" == 513"#, 1),
+            (r#"*(short*)"\xff\xff" == -1"#, 1),
+        ],
+    );
+}
+
+#[test]
+fn unsigned() {
+    check(
+        "unsigned",
+        &[
+            ("(unsigned)-1 > 0", 1),
+            ("-1 < (unsigned)0", 0),
+            ("(unsigned)-1 / 4294967296 == 4294967295", 1),
+            ("(long)-1 / 4294967296 == 0", 1),
+            ("(unsigned)7 / 2", 3),
+            ("(unsigned)-2 >= (unsigned)-1", 0),
+            ("(unsigned char)-1", 255),
+            ("(unsigned short)-1 == 65535", 1),
+            ("(signed char)255 < 0", 1),
+            (r#"*(unsigned char*)"\xff""#, 255),
+            (r#"*(unsigned short*)"\xff\xff" == 65535"#, 1),
+            // ポインタはアドレスなので符号なしで比べる
+            (r#"("abc" + 1) > "abc""#, 1),
+            // 配列へのポインタを参照した配列は、先頭のアドレスに読み替える
+            (r#"**(char(*)[3])"abc""#, 97),
+            (r#"(*(char(*)[3])"abc")[2]"#, 99),
+            (r#"*(*(char(*)[2])"abcd" + 1)"#, 98),
+        ],
+    );
+}
+
+/// Cのランタイムを使わずに`main`を呼び出し、その返り値で終了する開始ルーチン
+const START: &str = "\
+.intel_syntax noprefix
+.text
+.globl _start
+_start:
+  call main
+  mov rdi, rax
+  mov rax, 60
+  syscall
+";
+
+/// ソースコードをコンパイルし、GNU asとldで実行ファイルを作って実行したときの終了コードを返す
+fn run_with_ld(name: &str, source: &str) -> i32 {
+    let dir = env::temp_dir().join(format!("9cc-ld-{}-{}", std::process::id(), name));
+    let asm_path = dir.join("tmp.s");
+    let obj_path = dir.join("tmp.o");
+    let exe_path = dir.join("tmp");
+
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(&asm_path, format!("{}{}", START, compile(source).unwrap())).unwrap();
+
+    let status = Command::new("as")
+        .arg("-o")
+        .arg(&obj_path)
+        .arg(&asm_path)
+        .status()
+        .unwrap();
+
+    assert!(status.success(), "{}: アセンブルに失敗しました", source);
+
+    let status = Command::new("ld")
+        .arg("-o")
+        .arg(&exe_path)
+        .arg(&obj_path)
+        .status()
+        .unwrap();
+
+    assert!(status.success(), "{}: リンクに失敗しました", source);
+
+    let code = Command::new(&exe_path).status().unwrap().code().unwrap();
+
+    fs::remove_dir_all(&dir).unwrap();
+
+    code
+}
+
+#[test]
+#[ignore = "x86-64向けのGNU asとldが必要"]
+fn assemble_with_as_and_ld() {
+    let cases = [
+        ("42", 42),
+        ("5+20-4", 21),
+        ("(3+5)/2", 4),
+        ("-10+20", 10),
+        (r#"*"a" * 2"#, 194),
+        ("1 == 1", 1),
+        ("1 != 1", 0),
+        ("1 < 2", 1),
+        ("2 <= 1", 0),
+        ("2 > 1", 1),
+        ("1 >= 2", 0),
+        (r#""abc"[2] - "abc"[0]"#, 2),
+    ];
+
+    for (i, (source, expected)) in cases.iter().enumerate() {
+        assert_eq!(*expected, run_with_ld(&i.to_string(), source), "{}", source);
+    }
+}